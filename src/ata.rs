@@ -0,0 +1,329 @@
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+pub const SECTOR_SIZE: usize = 512;
+/// Primary and secondary channels, each with a master and a slave drive.
+pub const DISKS: usize = 4;
+
+/// Command block and control block base ports of the primary and secondary channels.
+const CHANNELS: [(u16, u16); 2] = [(0x1F0, 0x3F6), (0x170, 0x376)];
+
+// Offsets from the command block base.
+const DATA: u16 = 0;
+const SECTOR_COUNT: u16 = 2;
+const LBA_LOW: u16 = 3;
+const LBA_MID: u16 = 4;
+const LBA_HIGH: u16 = 5;
+const DRIVE: u16 = 6;
+const COMMAND: u16 = 7;
+const STATUS: u16 = 7;
+
+const STATUS_ERROR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_FAULT: u8 = 0x20;
+const STATUS_BUSY: u8 = 0x80;
+
+/// Turns off interrupts from the drives in the device control register; requests are polled.
+const CONTROL_NIEN: u8 = 0x02;
+/// Resets both drives of a channel while set in the device control register.
+const CONTROL_SRST: u8 = 0x04;
+
+const IDENTIFY: u8 = 0xEC;
+const READ_SECTORS: u8 = 0x20;
+const READ_SECTORS_EXT: u8 = 0x24;
+const WRITE_SECTORS: u8 = 0x30;
+const WRITE_SECTORS_EXT: u8 = 0x34;
+const FLUSH_CACHE: u8 = 0xE7;
+const FLUSH_CACHE_EXT: u8 = 0xEA;
+
+/// Number of status polls before a drive is considered unresponsive.
+const TIMEOUT: usize = 1_000_000;
+
+/// The highest sector reachable with 28-bit LBA.
+const LBA28_LIMIT: u64 = 1 << 28;
+
+/// Names of the drives, in the order of `DISKS` slots.
+pub const NAMES: [&str; DISKS] = ["hda", "hdb", "hdc", "hdd"];
+
+/// An ATA drive found by `init`.
+#[derive(Clone, Copy)]
+pub struct Disk {
+    base: u16,
+    slave: bool,
+    model: [u8; 40],
+    serial: [u8; 20],
+    /// Number of addressable sectors.
+    pub sectors: u64,
+    pub lba48: bool,
+}
+
+impl Disk {
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model).unwrap_or("").trim()
+    }
+
+    pub fn serial(&self) -> &str {
+        core::str::from_utf8(&self.serial).unwrap_or("").trim()
+    }
+}
+
+static DRIVES: Mutex<[Option<Disk>; DISKS]> = Mutex::new([None; DISKS]);
+
+fn read_port(base: u16, offset: u16) -> u8 {
+    let mut port: Port<u8> = Port::new(base + offset);
+    unsafe { port.read() }
+}
+
+fn write_port(base: u16, offset: u16, value: u8) {
+    let mut port: Port<u8> = Port::new(base + offset);
+    unsafe { port.write(value) }
+}
+
+/// Gives a newly selected drive the 400 ns it needs to show its status.
+fn settle(base: u16) {
+    for _ in 0..4 {
+        read_port(base, STATUS);
+    }
+}
+
+fn wait_not_busy(base: u16) -> Result<u8, &'static str> {
+    for _ in 0..TIMEOUT {
+        let status = read_port(base, STATUS);
+        if status & STATUS_BUSY == 0 {
+            return Ok(status);
+        }
+    }
+    Err("Disk timed out")
+}
+
+/// Waits until the drive is ready to transfer a sector.
+fn wait_data(base: u16) -> Result<(), &'static str> {
+    for _ in 0..TIMEOUT {
+        let status = read_port(base, STATUS);
+        if status & STATUS_BUSY != 0 {
+            continue;
+        }
+        if status & (STATUS_ERROR | STATUS_FAULT) != 0 {
+            return Err("Disk error");
+        }
+        if status & STATUS_DRQ != 0 {
+            return Ok(());
+        }
+    }
+    Err("Disk timed out")
+}
+
+/// Resets the channel of the drive at `base` after a failed transfer, so the next command does
+/// not find it stuck halfway through the last one.
+fn reset(base: u16) {
+    let Some(&(_, control)) = CHANNELS.iter().find(|&&(other, _)| other == base) else { return };
+    write_port(control, 0, CONTROL_SRST | CONTROL_NIEN);
+    // SRST has to stay set for at least 5 us; each read of the alternate status takes about 1 us
+    for _ in 0..8 {
+        read_port(control, 0);
+    }
+    write_port(control, 0, CONTROL_NIEN);
+    let _ = wait_not_busy(base);
+}
+
+/// Copies an IDENTIFY string, stored as big-endian words.
+fn identify_string(words: &[u16], output: &mut [u8]) {
+    for (index, word) in words.iter().enumerate() {
+        output[index * 2] = (word >> 8) as u8;
+        output[index * 2 + 1] = *word as u8;
+    }
+    for byte in output.iter_mut() {
+        if !byte.is_ascii_graphic() {
+            *byte = b' ';
+        }
+    }
+}
+
+fn identify(base: u16, slave: bool) -> Option<Disk> {
+    write_port(base, DRIVE, if slave { 0xB0 } else { 0xA0 });
+    settle(base);
+    // a floating bus reads 0xFF
+    if read_port(base, STATUS) == 0xFF {
+        return None;
+    }
+    for offset in SECTOR_COUNT..=LBA_HIGH {
+        write_port(base, offset, 0);
+    }
+    write_port(base, COMMAND, IDENTIFY);
+    if read_port(base, STATUS) == 0 {
+        return None;
+    }
+    wait_not_busy(base).ok()?;
+    // ATAPI and SATA devices abort IDENTIFY and leave their signature here
+    if read_port(base, LBA_MID) != 0 || read_port(base, LBA_HIGH) != 0 {
+        return None;
+    }
+    wait_data(base).ok()?;
+    let mut words = [0u16; 256];
+    let mut data: Port<u16> = Port::new(base + DATA);
+    for word in words.iter_mut() {
+        *word = unsafe { data.read() };
+    }
+
+    let lba48 = words[83] & (1 << 10) != 0;
+    let sectors = if lba48 {
+        words[100] as u64 | (words[101] as u64) << 16 | (words[102] as u64) << 32 | (words[103] as u64) << 48
+    } else {
+        words[60] as u64 | (words[61] as u64) << 16
+    };
+    let mut disk = Disk { base, slave, model: [0; 40], serial: [0; 20], sectors, lba48 };
+    identify_string(&words[27..47], &mut disk.model);
+    identify_string(&words[10..20], &mut disk.serial);
+    Some(disk)
+}
+
+/// Probes the master and slave drives of both channels.
+pub fn init() {
+    let mut drives = DRIVES.lock();
+    for (channel, &(base, control)) in CHANNELS.iter().enumerate() {
+        write_port(control, 0, CONTROL_NIEN);
+        for slave in [false, true] {
+            drives[channel * 2 + slave as usize] = identify(base, slave);
+        }
+    }
+}
+
+/// Returns the drive called `name` (hda to hdd), if present.
+pub fn find(name: &str) -> Option<usize> {
+    let index = NAMES.iter().position(|&other| other == name)?;
+    DRIVES.lock()[index].map(|_| index)
+}
+
+pub fn disk(index: usize) -> Option<Disk> {
+    DRIVES.lock().get(index).copied().flatten()
+}
+
+/// Resolves a device name: a whole drive (hda) or one of its MBR primary partitions (hda1 to
+/// hda4). Returns the drive, the first sector and the number of sectors.
+pub fn device(name: &str) -> Result<(usize, u64, u64), &'static str> {
+    let index = name.get(..3).and_then(find).ok_or("No such disk")?;
+    let sectors = disk(index).map_or(0, |disk| disk.sectors);
+    if name.len() == 3 {
+        return Ok((index, 0, sectors));
+    }
+    let number = match name[3..].parse::<usize>() {
+        Ok(number @ 1..=4) => number,
+        _ => return Err("No such partition"),
+    };
+    let mut mbr = [0; SECTOR_SIZE];
+    read(index, 0, &mut mbr)?;
+    if mbr[510..] != [0x55, 0xAA] {
+        return Err("No partition table");
+    }
+    let entry = &mbr[446 + (number - 1) * 16..][..16];
+    let start = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+    let count = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as u64;
+    // byte 4 is the partition type, 0 for an unused entry
+    if entry[4] == 0 || count == 0 {
+        return Err("No such partition");
+    }
+    if start + count > sectors {
+        return Err("Partition extends past the end of the disk");
+    }
+    Ok((index, start, count))
+}
+
+/// Selects the drive and sends a read or write command for `count` sectors at `lba`.
+fn start(disk: &Disk, lba: u64, count: usize, write: bool) -> Result<(), &'static str> {
+    let base = disk.base;
+    let slave = if disk.slave { 0x10 } else { 0 };
+    wait_not_busy(base)?;
+    if lba + count as u64 <= LBA28_LIMIT && count <= 256 {
+        write_port(base, DRIVE, 0xE0 | slave | (lba >> 24) as u8 & 0x0F);
+        settle(base);
+        // a count of 0 means 256 sectors
+        write_port(base, SECTOR_COUNT, count as u8);
+        write_port(base, LBA_LOW, lba as u8);
+        write_port(base, LBA_MID, (lba >> 8) as u8);
+        write_port(base, LBA_HIGH, (lba >> 16) as u8);
+        write_port(base, COMMAND, if write { WRITE_SECTORS } else { READ_SECTORS });
+    } else {
+        if !disk.lba48 {
+            return Err("Sector out of range for 28-bit LBA");
+        }
+        write_port(base, DRIVE, 0x40 | slave);
+        settle(base);
+        // the high bytes go first, then the low bytes; a count of 0 means 65536 sectors
+        write_port(base, SECTOR_COUNT, (count >> 8) as u8);
+        write_port(base, LBA_LOW, (lba >> 24) as u8);
+        write_port(base, LBA_MID, (lba >> 32) as u8);
+        write_port(base, LBA_HIGH, (lba >> 40) as u8);
+        write_port(base, SECTOR_COUNT, count as u8);
+        write_port(base, LBA_LOW, lba as u8);
+        write_port(base, LBA_MID, (lba >> 8) as u8);
+        write_port(base, LBA_HIGH, (lba >> 16) as u8);
+        write_port(base, COMMAND, if write { WRITE_SECTORS_EXT } else { READ_SECTORS_EXT });
+    }
+    Ok(())
+}
+
+/// Checks a transfer of whole sectors that fits on the drive and returns the sector count.
+fn check(disk: &Disk, lba: u64, length: usize) -> Result<usize, &'static str> {
+    let count = length / SECTOR_SIZE;
+    if !length.is_multiple_of(SECTOR_SIZE) || count == 0 || count > 65536 {
+        return Err("Transfers must be 1 to 65536 whole sectors");
+    }
+    if lba + count as u64 > disk.sectors {
+        return Err("Sector out of range");
+    }
+    Ok(count)
+}
+
+/// Reads whole sectors starting at `lba` into `buffer`.
+pub fn read(index: usize, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+    let disk = disk(index).ok_or("No such disk")?;
+    let count = check(&disk, lba, buffer.len())?;
+    let _drives = DRIVES.lock();
+    let result = read_sectors(&disk, lba, count, buffer);
+    if result.is_err() {
+        reset(disk.base);
+    }
+    result
+}
+
+fn read_sectors(disk: &Disk, lba: u64, count: usize, buffer: &mut [u8]) -> Result<(), &'static str> {
+    start(disk, lba, count, false)?;
+    let mut data: Port<u16> = Port::new(disk.base + DATA);
+    for sector in buffer.chunks_exact_mut(SECTOR_SIZE) {
+        wait_data(disk.base)?;
+        for pair in sector.chunks_exact_mut(2) {
+            pair.copy_from_slice(&unsafe { data.read() }.to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Writes whole sectors starting at `lba` from `data` and flushes the drive's write cache.
+pub fn write(index: usize, lba: u64, data: &[u8]) -> Result<(), &'static str> {
+    let disk = disk(index).ok_or("No such disk")?;
+    let count = check(&disk, lba, data.len())?;
+    let _drives = DRIVES.lock();
+    let result = write_sectors(&disk, lba, count, data);
+    if result.is_err() {
+        reset(disk.base);
+    }
+    result
+}
+
+fn write_sectors(disk: &Disk, lba: u64, count: usize, data: &[u8]) -> Result<(), &'static str> {
+    start(disk, lba, count, true)?;
+    let mut port: Port<u16> = Port::new(disk.base + DATA);
+    for sector in data.chunks_exact(SECTOR_SIZE) {
+        wait_data(disk.base)?;
+        for pair in sector.chunks_exact(2) {
+            unsafe { port.write(u16::from_le_bytes([pair[0], pair[1]])) };
+        }
+    }
+    wait_not_busy(disk.base)?;
+    write_port(disk.base, COMMAND, if disk.lba48 { FLUSH_CACHE_EXT } else { FLUSH_CACHE });
+    let status = wait_not_busy(disk.base)?;
+    if status & (STATUS_ERROR | STATUS_FAULT) != 0 {
+        return Err("Disk error");
+    }
+    Ok(())
+}
@@ -3,29 +3,92 @@
 use core::arch::asm;
 use core::fmt;
 use crate::{print, println, system_call, ERROR};
-use crate::vga_buffer::{WRITER, Color};
+use crate::vga_buffer::{self, WRITER, Color, CursorShape, StatusPosition, BUFFER_HEIGHT, BUFFER_WIDTH};
+use crate::ata;
+use crate::console;
+use crate::fat;
+use crate::fs;
+use crate::gfx;
 use crate::keyboard_buffer;
+use crate::keymap;
+use crate::mouse;
+use crate::ps2;
+use crate::screenshot;
+use crate::status;
+use crate::store;
+use crate::theme;
+use crate::vbe;
 
-const VGA_BUFFER: *mut u16 = 0xB8000 as *mut u16;
-const VGA_WIDTH: usize = 80;
-const VGA_HEIGHT: usize = 25;
 const RTC_PORT_INDEX: u16 = 0x70;
 const RTC_PORT_DATA: u16 = 0x71;
+/// Polls of the update-in-progress flag before the clock is considered missing. An update
+/// takes under 2 ms.
+const RTC_TIMEOUT: usize = 1_000_000;
 static mut BUFFER: &[u8] = b"";
-static mut INPUT_COLOR: Color = Color::White;
+/// Where the cursor was when `BUFFER` was captured.
+static mut BUFFER_CURSOR: (usize, usize) = (0, 0);
 
 pub fn architecture() {
     println!("x86_64");
 }
 
+/// Reads the time stamp counter.
+fn rdtsc() -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!(
+            "rdtsc",
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    (high as u64) << 32 | low as u64
+}
+
+pub fn bench(lines: &str) {
+    let lines = match lines {
+        "" => 1000,
+        lines => match lines.parse::<u64>() {
+            Ok(lines) if lines > 0 => lines,
+            _ => {
+                unsafe {
+                    ERROR = true;
+                }
+                println!("ERROR: Invalid number of lines");
+                return;
+            }
+        },
+    };
+    let start = rdtsc();
+    for line in 1..=lines {
+        println!("bench: line {} of {}", line, lines);
+        keyboard_buffer::poll_keys();
+    }
+    let cycles = rdtsc() - start;
+    println!("{} lines in {} cycles ({} per line)", lines, cycles, cycles / lines);
+}
+
 pub fn bootloader() {
     println!("bootloader v0.9 crate");
 }
 
 pub fn buffer() {
+    let shell_screen = WRITER.lock().save();
     clear();
-    unsafe {
-        print!("{}", core::str::from_utf8_unchecked(BUFFER));
+    WRITER.lock().set_cursor_shape(CursorShape::Block);
+    {
+        // put each cell back where it was, instead of printing, which wraps and scrolls
+        let mut writer = WRITER.lock();
+        let color_code = writer.color_code();
+        let width = vga_buffer::width();
+        unsafe {
+            let buffer = BUFFER;
+            for (index, &byte) in buffer.iter().enumerate() {
+                writer.write_at(index / width, index % width, byte, color_code);
+            }
+            writer.set_cursor_pos(BUFFER_CURSOR.0, BUFFER_CURSOR.1);
+        }
     }
     loop {
         let character = keyboard_buffer::read_char();
@@ -35,31 +98,33 @@ pub fn buffer() {
         }
         if character == '/' {
             clear();
-            println!();
         }
         if character != '\0' && character != '/' {
             print!("{}", character);
         }
     }
-    clear();
-    println!();
+    WRITER.lock().set_cursor_shape(CursorShape::Underline);
+    WRITER.lock().restore(&shell_screen);
 }
 
 pub fn screen() {
-    static mut VGA: [u8; VGA_WIDTH * VGA_HEIGHT] = [0; VGA_WIDTH * VGA_HEIGHT];
+    static mut VGA: [u8; BUFFER_WIDTH * BUFFER_HEIGHT] = [0; BUFFER_WIDTH * BUFFER_HEIGHT];
+    let (width, height) = (vga_buffer::width(), vga_buffer::height());
+    let writer = WRITER.lock();
     unsafe {
-        for row in 0..VGA_HEIGHT {
-            for col in 0..VGA_WIDTH {
-                let index = row * VGA_WIDTH + col;
-                let char_cell = *VGA_BUFFER.offset(index as isize);
-                VGA[index] = (char_cell & 0xFF) as u8;
+        for row in 0..height {
+            for col in 0..width {
+                let index = row * width + col;
+                VGA[index] = writer.read_at(row, col).0;
             }
         }
-        BUFFER = &VGA;
+        BUFFER = &VGA[..width * height];
+        BUFFER_CURSOR = writer.cursor_pos();
     }
 }
 
 pub fn calculator() {
+    let shell_screen = WRITER.lock().save();
     clear();
     loop {
         let mut buffer = [0u8; 32];
@@ -70,8 +135,7 @@ pub fn calculator() {
             if c == '\0' { continue; }
             
             if c == '\\' {
-                clear();
-                println!();
+                WRITER.lock().restore(&shell_screen);
                 return;
             }
 
@@ -80,6 +144,14 @@ pub fn calculator() {
                 break;
             }
 
+            if c == '\u{8}' {
+                if pos > 0 {
+                    print!("{}", c);
+                    pos -= 1;
+                }
+                continue;
+            }
+
             if (c.is_ascii_digit() || ['+', '-', '*', '/', '.', ' '].contains(&c)) && pos < buffer.len() {
                 print!("{}", c);
                 buffer[pos] = c as u8;
@@ -153,58 +225,119 @@ fn evaluate_expression(expr: &[u8]) -> Option<f64> {
     }
 }
 
+/// Prints the error of a failed filesystem command.
+fn report(result: Result<(), &'static str>) {
+    if let Err(message) = result {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: {}", message);
+    }
+}
+
+/// Splits the arguments of a command taking two paths.
+fn two_paths(input_str: &str) -> Result<(&str, &str), &'static str> {
+    match input_str.split_once(' ') {
+        Some((first, second)) if !second.trim().is_empty() => Ok((first, second.trim())),
+        _ => Err("Expected [source] [destination]"),
+    }
+}
+
+pub fn cat(path: &str) {
+    report(fs::open(path, fs::READ).and_then(|fd| {
+        let mut buffer = [0; 512];
+        let mut last = b'\n';
+        let result = loop {
+            match fs::read(fd, &mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(count) => {
+                    for &byte in &buffer[..count] {
+                        print!("{}", byte as char);
+                    }
+                    last = buffer[count - 1];
+                    keyboard_buffer::poll();
+                }
+                Err(message) => break Err(message),
+            }
+        };
+        fs::close(fd);
+        if last != b'\n' {
+            println!();
+        }
+        result
+    }));
+}
+
+pub fn cd(path: &str) {
+    report(fs::chdir(if path.is_empty() { "/" } else { path }));
+}
+
 pub fn clear() {
-    unsafe {
-        asm!(
-            "mov rdi, 0xB8000",
-            "mov rax, 0x20",
-            "mov rbx, 0x0F",
-            "mov rcx, 2000",
-            "2:",
-            "mov [rdi], ax",
-            "add rdi, 2",
-            "loop 2b",
-            options(nostack)
-        );
+    if vbe::active() && console::current() == console::visible() {
+        vbe::clear();
+        return;
     }
+    WRITER.lock().clear();
 }
 
-pub fn color(foreground: &str, background: Color) {
-    unsafe {
-        ERROR = false;
-        INPUT_COLOR = match foreground {
-            "black" => Color::Black,
-            "blue" => Color::Blue,
-            "green" => Color::Green,
-            "cyan" => Color::Cyan,
-            "red" => Color::Red,
-            "magenta" => Color::Magenta,
-            "brown" => Color::Brown,
-            "lightgray" => Color::LightGray,
-            "darkgray" => Color::DarkGray,
-            "lightblue" => Color::LightBlue,
-            "lightgreen" => Color::LightGreen,
-            "lightcyan" => Color::LightCyan,
-            "lightred" => Color::LightRed,
-            "pink" => Color::Pink,
-            "yellow" => Color::Yellow,
-            "white" => Color::White,
-            "" => {
+const COLOR_NAMES: [&str; 16] = [
+    "black", "blue", "green", "cyan", "red", "magenta", "brown", "lightgray",
+    "darkgray", "lightblue", "lightgreen", "lightcyan", "lightred", "pink", "yellow", "white",
+];
+
+fn parse_color(name: &str) -> Option<Color> {
+    COLOR_NAMES.iter().position(|&color| color == name).map(|index| Color::from_index(index as u8))
+}
+
+pub fn blink(input_str: &str) {
+    match input_str {
+        "" => println!("{}", if vga_buffer::blink() { "on" } else { "off" }),
+        "on" => vga_buffer::set_blink(true),
+        "off" => vga_buffer::set_blink(false),
+        _ => {
+            unsafe {
                 ERROR = true;
-                println!("ERROR: No color specified");
-                return;
             }
-            _ => {
-                ERROR = true;
-                println!("ERROR: Invalid color");
-                return;
-            },
-        };
-        let mut writer = WRITER.lock();
-        writer.color(INPUT_COLOR, background);
+            println!("ERROR: blink command requires on or off");
+        }
     }
 }
 
+pub fn color(input_str: &str) {
+    let mut arguments = input_str.split_whitespace();
+    let foreground = match arguments.next().map(parse_color) {
+        Some(Some(color)) => color,
+        Some(None) => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: Invalid color");
+            return;
+        }
+        None => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: No color specified");
+            return;
+        }
+    };
+    let mut writer = WRITER.lock();
+    let background = match arguments.next().map(parse_color) {
+        Some(Some(color)) => color,
+        Some(None) => {
+            drop(writer);
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: Invalid color");
+            return;
+        }
+        None => writer.color_code().background(),
+    };
+    writer.color(foreground, background);
+}
+
 pub fn cpu() {
     let mut brand_string = [0u8; 48];
     let mut regs: [u32; 4] = [0; 4];
@@ -246,13 +379,21 @@ pub fn cpu() {
     }
 }
 
+pub fn cp(input_str: &str) {
+    report(two_paths(input_str).and_then(|(source, destination)| fs::copy(source, destination)));
+}
+
 pub fn delay(cycles: &str) {
     match cycles.parse::<u64>() {
         Ok(cycles) => {
             unsafe {
                 ERROR = false;
             }
-            for _ in 0..cycles {}
+            for cycle in 0..cycles {
+                if cycle % 0x10000 == 0 {
+                    keyboard_buffer::poll();
+                }
+            }
         }
         Err(_) => {
             unsafe {
@@ -263,12 +404,67 @@ pub fn delay(cycles: &str) {
     }
 }
 
+pub fn df() {
+    println!("{:<16} {:>10} {:>10} {:>10} Mounted on", "Filesystem", "KiB", "Used", "Available");
+    fs::mounts(|path, device, _, (size, used)| {
+        println!("{:<16} {:>10} {:>10} {:>10} {}", device, size / 1024, used / 1024, (size - used) / 1024, path);
+    });
+}
+
+/// Lists the ATA drives, or dumps a sector of one.
+pub fn disks(input_str: &str) {
+    if input_str.is_empty() {
+        let mut found = false;
+        for (index, name) in ata::NAMES.iter().enumerate() {
+            if let Some(disk) = ata::disk(index) {
+                println!(
+                    "{}: {}, serial {}, {} sectors ({} MiB), {}-bit LBA",
+                    name,
+                    disk.model(),
+                    disk.serial(),
+                    disk.sectors,
+                    disk.sectors * ata::SECTOR_SIZE as u64 / (1024 * 1024),
+                    if disk.lba48 { 48 } else { 28 }
+                );
+                found = true;
+            }
+        }
+        if !found {
+            println!("No ATA disks found");
+        }
+        return;
+    }
+    let (name, sector) = input_str.split_once(' ').unwrap_or((input_str, "0"));
+    let mut buffer = [0; ata::SECTOR_SIZE];
+    let result = match (ata::find(name), sector.trim().parse::<u64>()) {
+        (None, _) => Err("No such disk"),
+        (_, Err(_)) => Err("Invalid sector"),
+        (Some(index), Ok(sector)) => ata::read(index, sector, &mut buffer),
+    };
+    if result.is_ok() {
+        for (row, bytes) in buffer.chunks(16).enumerate() {
+            print!("{:03x}:", row * 16);
+            for byte in bytes {
+                print!(" {:02x}", byte);
+            }
+            print!("  ");
+            for &byte in bytes {
+                print!("{}", if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+            }
+            println!();
+        }
+    }
+    report(result);
+}
+
 pub fn echo(input: &str) {
     println!("{}", input);
 }
 
 pub fn ephemeral() {
+    let shell_screen = WRITER.lock().save();
     clear();
+    WRITER.lock().set_cursor_shape(CursorShape::Block);
     loop {
         let character = keyboard_buffer::read_char();
         if character == '\\' {
@@ -276,18 +472,134 @@ pub fn ephemeral() {
         }
         if character == '/' {
             clear();
-            println!();
         }
         if character != '\0' && character != '/' {
             print!("{}", character);
         }
     }
-    clear();
-    println!();
+    WRITER.lock().set_cursor_shape(CursorShape::Underline);
+    WRITER.lock().restore(&shell_screen);
+}
+
+pub fn font(name: &str) {
+    if name.is_empty() {
+        println!("Font: {} ({} scan lines)", vga_buffer::font(), vga_buffer::cell_height());
+        print!("Available:");
+        for font in vga_buffer::FONTS.iter() {
+            print!(" {}", font);
+        }
+        println!();
+        return;
+    }
+    let result = match name.strip_prefix("load ") {
+        Some(path) => load_font(path.trim()),
+        None => vga_buffer::set_font(name),
+    };
+    report(result);
+}
+
+/// Loads a PSF font file; only the glyphs used are read.
+fn load_font(path: &str) -> Result<(), &'static str> {
+    let mut data = [0; vga_buffer::PSF_SIZE];
+    let fd = fs::open(path, fs::READ)?;
+    let mut length = 0;
+    let result = loop {
+        match fs::read(fd, &mut data[length..]) {
+            Ok(0) => break Ok(()),
+            Ok(count) => length += count,
+            Err(message) => break Err(message),
+        }
+        if length == data.len() {
+            break Ok(());
+        }
+    };
+    fs::close(fd);
+    result?;
+    vga_buffer::load_psf(&data[..length])
+}
+
+pub fn gfx() {
+    // a 16x16 smiley, one bit per pixel
+    const SPRITE: [u16; 16] = [
+        0x07E0, 0x1FF8, 0x3FFC, 0x7FFE, 0x73CE, 0xF3CF, 0xFFFF, 0xFFFF,
+        0xFFFF, 0xDFFB, 0xEFF7, 0x77EE, 0x781E, 0x3FFC, 0x1FF8, 0x07E0,
+    ];
+    if vbe::active() {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: Leave the VBE mode first (vbe off)");
+        return;
+    }
+    let mut sprite = [0xFFu8; 256];
+    for (row, bits) in SPRITE.iter().enumerate() {
+        for col in 0..16 {
+            if bits & (0x8000 >> col) != 0 {
+                sprite[row * 16 + col] = Color::Yellow as u8;
+            }
+        }
+    }
+
+    gfx::enter();
+    println!("Mode 13h: 320x200, 256 colors");
+    println!("Press any key to return to text mode");
+
+    gfx::rect(0, 40, 100, 100, Color::White as u8);
+    for step in 0..10 {
+        gfx::line(1, 41, 98, 41 + step * 11, gfx::rgb(5, step as u8 / 2, 0));
+        gfx::line(1, 41, 1 + step * 11, 138, gfx::rgb(0, step as u8 / 2, 5));
+    }
+
+    // shades of the standard light red
+    let (red, green, blue) = gfx::palette(Color::LightRed as u8);
+    for step in 0..8u8 {
+        let shade = |value: u8| (value as u16 * (step as u16 + 1) / 8) as u8;
+        gfx::set_palette(248 + step, (shade(red), shade(green), shade(blue)));
+    }
+    for step in 0..8 {
+        gfx::fill_rect(110 + step * 10, 40 + step * 6, 40, 30, 248 + step as u8);
+        gfx::rect(110 + step * 10, 40 + step * 6, 40, 30, Color::White as u8);
+    }
+
+    gfx::fill_circle(265, 75, 30, gfx::rgb(0, 3, 1));
+    for radius in (4..30).step_by(6) {
+        gfx::circle(265, 75, radius, gfx::rgb(1, 5, 2));
+    }
+    gfx::blit(257, 117, 16, &sprite, Some(0xFF));
+    // an upside-down copy read back from the screen
+    for y in 0..16 {
+        for x in 0..16 {
+            gfx::set_pixel(277 + x, 132 - y, gfx::pixel(257 + x, 117 + y));
+        }
+    }
+    gfx::draw_text(215, 140, "blit", Color::LightGray as u8, None);
+
+    for index in 0..=255u8 {
+        let x = 32 + index as i32;
+        gfx::line(x, 160, x, 190, index);
+    }
+
+    loop {
+        if keyboard_buffer::read_char() != '\0' {
+            break;
+        }
+    }
+    gfx::leave();
+}
+
+/// Saves the variable before halting or rebooting, showing any error until a key is pressed.
+fn autosave() {
+    if let Err(message) = store::autosave() {
+        report(Err(message));
+        println!("The variable was not saved. Press any key to continue");
+        while keyboard_buffer::read_char() == '\0' {}
+    }
 }
 
 pub fn halt() {
+    autosave();
     clear();
+    WRITER.lock().set_cursor_visible(false);
     print!("CPU Halted");
     unsafe {
         asm!("hlt");
@@ -296,7 +608,7 @@ pub fn halt() {
 }
 
 pub fn help() {
-    println!("architecture\nbootloader\nbuffer\ncalculator\nclear\ncolor [color]\ncpu\ndelay [cycles]\necho [message]\nephemeral\nhalt\nhelp\ninfo\nls\nmanual\nmv [previous text] [updated text]\npurge\nreboot\nrm [text]\ntime\ntouch [text]\nuptime\nvendor\nversion");
+    println!("architecture\nbench [lines]\nblink [on/off]\nbootloader\nbuffer\ncalculator\ncat [path]\ncd [path]\nclear\ncolor [foreground] [background]\ncpu\ncp [source] [destination]\ndelay [cycles]\ndf\ndisks [name] [sector]\necho [message]\nephemeral\nfont [name]\nfont load [path]\ngfx\nhalt\nhelp\ninfo\nkbdrate [delay] [rate]\nkeymap [layout]\nload\nls [path]\nmanual\nmkdir [path]\nmkfs.fat [device]\nmode [columns]x[rows]\nmount [type] [path] [device]\nmouse\nmv [source] [destination]\npalette [color] [red] [green] [blue]\npurge\npwd\nreboot\nrm [path]\nrmdir [path]\nsave\nscreenshot [name]\nstatus [top/bottom/off]\ntheme [name]\ntime\ntouch [path]\numount [path]\nuptime\nvar [ls/touch/rm/mv/purge] [text]\nvbe [WIDTHxHEIGHT/off]\nvendor\nversion\nwrite [path] [text]");
 }
 
 pub fn info() {
@@ -306,6 +618,7 @@ pub fn info() {
     bootloader();
     print!("CPU: ");
     cpu();
+    println!("PS/2 Ports: {}", if ps2::dual_channel() { 2 } else { 1 });
     print!("Time: ");
     time();
     print!("Uptime: ");
@@ -316,73 +629,336 @@ pub fn info() {
     version();
 }
 
+pub fn kbdrate(input_str: &str) {
+    let mut arguments = input_str.split_whitespace();
+    let (delay, rate) = match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(delay), Some(rate), None) => (delay, rate),
+        _ => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: kbdrate command requires [delay] and [rate]");
+            return;
+        }
+    };
+    let delay = match delay.parse::<u16>() {
+        Ok(delay) if ps2::DELAYS.contains(&delay) => delay,
+        _ => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: Delay must be 250, 500, 750 or 1000");
+            return;
+        }
+    };
+    let rate = match rate.parse::<u16>() {
+        Ok(rate) if (2..=30).contains(&rate) => rate,
+        _ => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: Rate must be between 2 and 30");
+            return;
+        }
+    };
+    match ps2::set_typematic(delay, rate) {
+        Some(tenths) => println!("Repeat after {} ms at {}.{} characters per second", delay, tenths / 10, tenths % 10),
+        None => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: Keyboard did not accept the typematic rate");
+        }
+    }
+}
+
+pub fn keymap(name: &str) {
+    if name.is_empty() {
+        print!("Active: {}\nAvailable:", keymap::active().name);
+        for layout in keymap::KEYMAPS.iter() {
+            print!(" {}", layout.name);
+        }
+        println!();
+        return;
+    }
+    if !keymap::select(name) {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: Invalid keymap");
+    }
+}
+
+pub fn ls(path: &str) {
+    let path = if path.is_empty() { "." } else { path };
+    report(fs::stat(path).and_then(|stat| {
+        if !stat.directory {
+            println!("{:<32} {}", path, stat.size);
+            return Ok(());
+        }
+        fs::readdir(path, |name, stat| {
+            if stat.directory {
+                println!("{}/", name);
+            } else {
+                println!("{:<32} {}", name, stat.size);
+            }
+        })
+    }));
+}
+
 pub fn manual() {
     println!("architecture: Displays the system architecture (x86_64).
+bench [lines]: Prints a number of lines (1000 by default) and reports the CPU cycles it took.
+blink [on/off]: Makes bright backgrounds blink (on) or show as bright colors (off), or shows the setting.
 bootloader: Information about the bootloader (bootloader v0.9 crate).
 buffer: Buffer Text Editor.
 calculator: Interactive calculator mode.
+cat [path]: Displays the contents of a file.
+cd [path]: Changes the working directory (/ by default).
 clear: Clears the screen.
-color [color]: Changes the text color.
+color [foreground] [background]: Changes the text colors.
 cpu: Displays the CPU brand string.
+cp [source] [destination]: Copies a file.
 delay [cycles]: Sleeps for the specified number of cycles.
+df: Displays the size and free space of the mounted filesystems.
+disks [name] [sector]: Lists the ATA disks with their model, serial number and size, or dumps a sector of one (hda to hdd) in hexadecimal.
 echo [message]: Echoes a message.
 ephemeral: Ephemeral Text Editor.
-halt: Halts the CPU.
+font [name]: Loads a text mode font (default, thin, bold, dyslexic), or shows the current one.
+font load [path]: Loads a PSF1 or PSF2 font file with glyphs 8 pixels wide.
+gfx: Shows a 320x200 256-color graphics demo until a key is pressed.
+halt: Saves the variable if a disk holds it, then halts the CPU.
 help: Lists all available commands.
 info: Displays system information.
-ls: Displays the contents of the variable.
+kbdrate [delay] [rate]: Sets the key repeat delay (ms) and rate (characters per second).
+keymap [layout]: Selects the keyboard layout (us, uk, de, fr, dvorak).
+load: Replaces the variable with the copy saved on disk.
+ls [path]: Lists a directory (the working directory by default) with file sizes.
 manual: Displays the system manual.
-mv [previous text] [updated text]: Replaces previous text with updated text.
-purge: Deletes all the text in the variable.
-reboot: Reboots the system.
-rm [text]: Removes the specified text from the variable.
+mkdir [path]: Creates a directory.
+mkfs.fat [device]: Formats a disk or partition (hda, hda1) with FAT12, FAT16 or FAT32 depending on its size, erasing it.
+mode [columns]x[rows]: Switches the text mode (80x25, 80x50, 90x30, 90x60), or lists the modes.
+mount [type] [path] [device]: Mounts a filesystem (ramfs, or ext2 or fat on a device such as hda or hda1) on a directory, or lists the mounted filesystems.
+mouse: Displays mouse events until '\\' is pressed.
+mv [source] [destination]: Moves or renames a file or directory.
+palette [color] [red] [green] [blue]: Sets the RGB value (0-255) of a color, or lists all colors.
+purge: Deletes all the text in the variable (/variable).
+pwd: Displays the working directory.
+reboot: Saves the variable if a disk holds it, then reboots the system.
+rm [path]: Deletes a file.
+rmdir [path]: Deletes an empty directory.
+save: Saves the variable to the sectors before the first partition of the first partitioned disk, to be loaded at boot.
+screenshot [name]: Saves the screen with its colors under a name, or lists the screenshots. 'screenshot show [name]' shows one until a key is pressed, 'screenshot text [name]' and 'screenshot ansi [name]' send it to the serial port as plain or ANSI-colored text, 'screenshot rm [name]' deletes it.
+status [top/bottom/off]: Shows the status row (clock, uptime, console, lock keys, errors) at the top or bottom, or hides it.
+theme [name]: Applies a color theme (default, solarized, amber, green).
 time: Displays the Real Time Clock.
-touch [text]: Appends your text to a variable.
+touch [path]: Creates an empty file.
+umount [path]: Unmounts the filesystem mounted on a directory.
 uptime: Displays the system uptime.
+var [ls/touch/rm/mv/purge] [text]: Shows the variable (/variable), appends text to it, removes a word from it, replaces a word with new text, or deletes all of it.
+vbe [WIDTHxHEIGHT/off]: Switches to a Bochs VBE mode (default 1024x768, 32 bits per pixel) with a text console, or back to text mode.
 vendor: Displays CPU vendor string.
-version: Displays the kernel version.");
+version: Displays the kernel version.
+write [path] [text]: Replaces the contents of a file with the text, creating it if needed.");
 }
 
-pub fn mv(input_str: &str){
-    let trimmed = input_str;
-    if let Some(space_idx) = trimmed.find(' ') {
-        let prev = &trimmed[..space_idx];
-        let updated = trimmed[space_idx + 1..].trim();
-        if prev.is_empty() || updated.is_empty() {
-            unsafe {
-                ERROR = true;
-            }
-            println!("ERROR: mv command requires [previous text] and [updated text]");
-        } else {
-            system_call(2, prev.as_bytes());
-            unsafe {
-                if ERROR == true {
-                    return;
-                }
-                system_call(3, updated.as_bytes());
-                if ERROR == true {
-                    system_call(3, prev.as_bytes());
-                }
-            }
+pub fn load() {
+    match store::load() {
+        Ok(Some((disk, length))) => println!("Loaded {} bytes from {}", length, disk),
+        Ok(None) => report(Err("Nothing has been saved")),
+        Err(message) => report(Err(message)),
+    }
+}
+
+pub fn mkdir(path: &str) {
+    report(fs::mkdir(path));
+}
+
+/// Formats a disk or partition with a FAT filesystem sized for it.
+pub fn mkfs_fat(device: &str) {
+    match fat::format(device) {
+        Ok((kind, cluster_size)) => println!("Formatted {} as {} with {}-byte clusters", device, kind, cluster_size),
+        Err(message) => report(Err(message)),
+    }
+}
+
+/// Parses a size written as `WIDTHxHEIGHT`.
+fn parse_size(input_str: &str) -> Option<(usize, usize)> {
+    let (width, height) = input_str.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+pub fn mode(input_str: &str) {
+    if input_str.is_empty() {
+        println!("Mode: {}x{}", vga_buffer::width(), vga_buffer::height());
+        print!("Available:");
+        for mode in vga_buffer::TEXT_MODES.iter() {
+            print!(" {}x{}", mode.width, mode.height);
         }
-    } else {
+        println!();
+        return;
+    }
+    let result = match parse_size(input_str) {
+        Some((width, height)) => vga_buffer::set_mode(width, height),
+        None => Err("Expected COLUMNSxROWS"),
+    };
+    if let Err(message) = result {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: {}", message);
+    }
+}
+
+pub fn mouse() {
+    if !mouse::present() {
         unsafe {
             ERROR = true;
         }
-        println!("ERROR: mv command requires [previous text] and [updated text]");
+        println!("ERROR: No mouse detected");
+        return;
+    }
+    while mouse::poll_event().is_some() {}
+    loop {
+        let character = keyboard_buffer::read_char();
+        if character == '\\' {
+            break;
+        }
+        while let Some(event) = mouse::poll_event() {
+            println!("Row: {} Column: {} Buttons: {:03b} Wheel: {}", event.row, event.col, event.buttons, event.wheel);
+        }
+    }
+}
+
+/// Mounts a filesystem, or lists the mounted ones.
+pub fn mount(input_str: &str) {
+    if input_str.is_empty() {
+        fs::mounts(|path, device, kind, _| println!("{} on {} type {}", device, path, kind));
+        return;
+    }
+    let mut arguments = input_str.split_whitespace();
+    let (kind, path, device) = (arguments.next(), arguments.next(), arguments.next().unwrap_or(""));
+    report(match (kind, path) {
+        (Some(kind), Some(path)) => fs::mount(kind, path, device),
+        _ => Err("Expected [type] [path] [device]"),
+    });
+}
+
+pub fn mv(input_str: &str) {
+    report(two_paths(input_str).and_then(|(source, destination)| fs::rename(source, destination)));
+}
+
+pub fn palette(input_str: &str) {
+    if input_str.is_empty() {
+        for (index, name) in COLOR_NAMES.iter().enumerate() {
+            let (red, green, blue) = vga_buffer::palette(Color::from_index(index as u8));
+            println!("{:<10} {:>3} {:>3} {:>3}", name, red << 2 | red >> 4, green << 2 | green >> 4, blue << 2 | blue >> 4);
+        }
+        return;
+    }
+    let mut arguments = input_str.split_whitespace();
+    let color = arguments.next().and_then(parse_color);
+    let mut rgb = [0u8; 3];
+    let mut valid = color.is_some();
+    for value in rgb.iter_mut() {
+        match arguments.next().map(|argument| argument.parse::<u8>()) {
+            Some(Ok(parsed)) => *value = parsed >> 2,
+            _ => valid = false,
+        }
+    }
+    match color {
+        Some(color) if valid && arguments.next().is_none() => vga_buffer::set_palette(color, (rgb[0], rgb[1], rgb[2])),
+        _ => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: palette command requires [color] [red] [green] [blue] (0-255)");
+        }
     }
 }
 
+pub fn pwd() {
+    fs::cwd(|path| println!("{}", path));
+}
+
 pub fn reboot() {
+    autosave();
+    restart();
+}
+
+/// Reboots at once without saving anything, for SysRq and Ctrl+Alt+Del.
+pub fn restart() {
     unsafe {
         asm!("int 0x19");
     }
 }
 
-struct Time {
-    seconds: u8,
-    minutes: u8,
-    hours: u8,
+pub fn rm(path: &str) {
+    report(fs::unlink(path));
+}
+
+pub fn rmdir(path: &str) {
+    report(fs::rmdir(path));
+}
+
+pub fn save() {
+    match store::save() {
+        Ok((disk, length)) => println!("Saved {} bytes to {}", length, disk),
+        Err(message) => report(Err(message)),
+    }
+}
+
+pub fn screenshot(input_str: &str) {
+    let (action, name) = match input_str.split_once(' ') {
+        Some((action, name)) => (action, name.trim()),
+        None => ("", input_str),
+    };
+    let result = match action {
+        "" if name.is_empty() => {
+            let mut count = 0;
+            screenshot::list(|name, (width, height)| {
+                println!("{} ({}x{})", name, width, height);
+                count += 1;
+            });
+            println!("{} of {} slots used", count, screenshot::SLOTS);
+            Ok(())
+        }
+        "" => screenshot::capture(name),
+        "show" => show_screenshot(name),
+        "text" => screenshot::export(name, false),
+        "ansi" => screenshot::export(name, true),
+        "rm" => {
+            if screenshot::delete(name) { Ok(()) } else { Err("No such screenshot") }
+        }
+        _ => Err("Expected a name, or show, text, ansi or rm and a name"),
+    };
+    if let Err(message) = result {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: {}", message);
+    }
+}
+
+/// Shows a screenshot, in the text mode it was taken in, until a key is pressed.
+fn show_screenshot(name: &str) -> Result<(), &'static str> {
+    let shot = screenshot::find(name).ok_or("No such screenshot")?;
+    let size = (vga_buffer::width(), vga_buffer::height());
+    let shell_screen = WRITER.lock().save();
+    if shot.size() != size {
+        vga_buffer::set_mode(shot.size().0, shot.size().1)?;
+    }
+    WRITER.lock().restore(&shot);
+    while keyboard_buffer::read_char() == '\0' {}
+    let result = if shot.size() != size { vga_buffer::set_mode(size.0, size.1) } else { Ok(()) };
+    WRITER.lock().restore(&shell_screen);
+    result
+}
+
+pub struct Time {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
 }
 
 impl fmt::Display for Time {
@@ -395,8 +971,69 @@ fn bcd_to_decimal(bcd: u8) -> u8 {
     ((bcd >> 4) * 10) + (bcd & 0x0F)
 }
 
+pub fn theme(name: &str) {
+    if name.is_empty() {
+        print!("Available:");
+        for theme in theme::THEMES.iter() {
+            print!(" {}", theme.name);
+        }
+        println!();
+        return;
+    }
+    if !theme::apply(name) {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: Invalid theme");
+    }
+}
+
+pub fn status(position: &str) {
+    let position = match position {
+        "" => {
+            match vga_buffer::status_position() {
+                Some(StatusPosition::Top) => println!("Status row: top"),
+                Some(StatusPosition::Bottom) => println!("Status row: bottom"),
+                None => println!("Status row: off"),
+            }
+            return;
+        }
+        "top" => Some(StatusPosition::Top),
+        "bottom" => Some(StatusPosition::Bottom),
+        "off" => None,
+        _ => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: Expected top, bottom or off");
+            return;
+        }
+    };
+    vga_buffer::set_status_position(position);
+    status::refresh();
+}
+
 pub fn time() {
+    match read_rtc() {
+        Ok(time) => println!("UTC: {}", time),
+        Err(message) => report(Err(message)),
+    }
+}
+
+/// Reads the current time from the Real Time Clock.
+pub fn read_rtc() -> Result<Time, &'static str> {
     unsafe {
+        // wait for a running update to finish so the fields belong to the same second
+        outb(RTC_PORT_INDEX, 0x0A);
+        let mut polls = 0;
+        while inb(RTC_PORT_DATA) & 0x80 != 0 {
+            polls += 1;
+            if polls == RTC_TIMEOUT {
+                return Err("The Real Time Clock is not responding");
+            }
+            keyboard_buffer::poll_keys();
+        }
+
         outb(RTC_PORT_INDEX, 0x00);
         let bcd_seconds = inb(RTC_PORT_DATA);
 
@@ -406,13 +1043,11 @@ pub fn time() {
         outb(RTC_PORT_INDEX, 0x04);
         let bcd_hours = inb(RTC_PORT_DATA);
 
-        let time = Time {
+        Ok(Time {
             seconds: bcd_to_decimal(bcd_seconds),
             minutes: bcd_to_decimal(bcd_minutes),
             hours: bcd_to_decimal(bcd_hours),
-        };
-        
-        println!("UTC: {}", time);
+        })
     }
 }
 
@@ -426,6 +1061,15 @@ unsafe fn inb(port: u16) -> u8 {
     value
 }
 
+/// Creates an empty file unless it already exists.
+pub fn touch(path: &str) {
+    report(fs::open(path, fs::CREATE).map(fs::close));
+}
+
+pub fn umount(path: &str) {
+    report(fs::umount(path));
+}
+
 pub fn uptime() {
     let mut tsc: u64;
     unsafe {
@@ -439,6 +1083,75 @@ pub fn uptime() {
     println!("{} cycles", tsc);
 }
 
+/// Works on the whitespace-separated tokens of the variable, kept in /variable.
+pub fn var(input_str: &str) {
+    let (operation, text) = input_str.split_once(' ').unwrap_or((input_str, ""));
+    let text = text.trim();
+    match operation {
+        "" | "ls" => system_call(0, b""),
+        "purge" => system_call(1, b""),
+        "rm" => system_call(2, text.as_bytes()),
+        "touch" => system_call(3, text.as_bytes()),
+        "mv" => match text.split_once(' ') {
+            Some((previous, updated)) if !updated.trim().is_empty() => {
+                system_call(2, previous.as_bytes());
+                if unsafe { ERROR } {
+                    return;
+                }
+                system_call(3, updated.trim().as_bytes());
+                // put the previous text back if the updated text did not fit
+                if unsafe { ERROR } {
+                    system_call(3, previous.as_bytes());
+                    unsafe {
+                        ERROR = true;
+                    }
+                }
+            }
+            _ => report(Err("Expected [previous text] [updated text]")),
+        },
+        _ => report(Err("Expected ls, touch, rm, mv or purge")),
+    }
+}
+
+pub fn vbe(input_str: &str) {
+    let (width, height) = match input_str {
+        "" => (1024, 768),
+        "off" => {
+            vbe::leave();
+            return;
+        }
+        _ => match parse_size(input_str) {
+            Some(size) => size,
+            None => {
+                unsafe {
+                    ERROR = true;
+                }
+                println!("ERROR: Expected WIDTHxHEIGHT or off");
+                return;
+            }
+        },
+    };
+    if gfx::active() {
+        unsafe {
+            ERROR = true;
+        }
+        println!("ERROR: Mode 13h is active");
+        return;
+    }
+    match vbe::enter(width, height) {
+        Ok(()) => {
+            let (columns, rows) = vbe::text_size();
+            println!("VBE: {}x{}x32, {}x{} characters", width, height, columns, rows);
+        }
+        Err(message) => {
+            unsafe {
+                ERROR = true;
+            }
+            println!("ERROR: {}", message);
+        }
+    }
+}
+
 pub fn vendor() {
     let mut regs: [u32; 4] = [0; 4];
 
@@ -477,4 +1190,14 @@ pub fn vendor() {
 pub fn version() {
     println!("h-3x Kernel v1.0.0-beta");
 
-}
\ No newline at end of file
+}
+
+/// Replaces the contents of a file with the text, creating the file if needed.
+pub fn write(input_str: &str) {
+    let (path, text) = input_str.split_once(' ').unwrap_or((input_str, ""));
+    report(fs::open(path, fs::WRITE | fs::CREATE | fs::TRUNCATE).and_then(|fd| {
+        let result = fs::write(fd, text.trim().as_bytes()).and_then(|_| fs::write(fd, b"\n"));
+        fs::close(fd);
+        result.map(|_| ())
+    }));
+}
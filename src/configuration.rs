@@ -1,37 +1,119 @@
-use crate::vga_buffer::Color;
+use spin::Mutex;
 use crate::{println, system_call, ERROR};
 use crate::commands;
+use crate::status;
+use crate::console::{self, CONSOLES};
+
+#[derive(Clone, Copy)]
+struct Running {
+    command: [u8; 80],
+    length: usize,
+}
+
+/// The command line being executed on each console.
+static RUNNING: Mutex<[Running; CONSOLES]> = Mutex::new([Running {
+    command: [0; 80],
+    length: 0,
+}; CONSOLES]);
+
+/// Calls `f` with the command line currently being executed on the running task's console,
+/// or `None` at the prompt.
+pub fn with_running<F: FnOnce(Option<&str>)>(f: F) {
+    let running = &RUNNING.lock()[console::current()];
+    if running.length == 0 {
+        f(None);
+    } else {
+        f(core::str::from_utf8(&running.command[..running.length]).ok());
+    }
+}
+
+fn set_running(input_str: &str) {
+    let running = &mut RUNNING.lock()[console::current()];
+    let length = input_str.len().min(running.command.len());
+    running.command[..length].copy_from_slice(&input_str.as_bytes()[..length]);
+    running.length = length;
+}
 
 pub fn execute(input_str: &str) {
     unsafe {
         ERROR = false;
     }
+    set_running(input_str.trim());
+    dispatch(input_str);
+    set_running("");
+    status::refresh();
+}
+
+fn dispatch(input_str: &str) {
     match input_str.trim() {
         "architecture" => commands::architecture(),
+        "bench" => commands::bench(""),
+        "blink" => commands::blink(""),
         "bootloader" => commands::bootloader(),
         "buffer" => commands::buffer(),
         "calculator" => commands::calculator(),
+        "cd" => commands::cd(""),
         "clear" => commands::clear(),
         "cpu" => commands::cpu(),
+        "df" => commands::df(),
+        "disks" => commands::disks(""),
         "ephemeral" => commands::ephemeral(),
+        "font" => commands::font(""),
+        "gfx" => commands::gfx(),
         "halt" => commands::halt(),
         "help" => commands::help(),
         "info" => commands::info(),
-        "ls" => system_call(0, b""),
+        "keymap" => commands::keymap(""),
+        "load" => commands::load(),
+        "ls" => commands::ls(""),
         "manual" => commands::manual(),
+        "mode" => commands::mode(""),
+        "mount" => commands::mount(""),
+        "mouse" => commands::mouse(),
+        "palette" => commands::palette(""),
         "purge" => system_call(1, b""),
+        "pwd" => commands::pwd(),
         "reboot" => commands::reboot(),
+        "save" => commands::save(),
+        "screenshot" => commands::screenshot(""),
+        "status" => commands::status(""),
+        "theme" => commands::theme(""),
         "time" => commands::time(),
         "uptime" => commands::uptime(),
+        "var" => commands::var(""),
+        "vbe" => commands::vbe(""),
         "vendor" => commands::vendor(),
         "version" => commands::version(),
         "" => (),
-        _ if input_str.starts_with("color ") => commands::color(&input_str[6..].trim(), Color::Black),
+        _ if input_str.starts_with("bench ") => commands::bench(input_str[6..].trim()),
+        _ if input_str.starts_with("blink ") => commands::blink(input_str[6..].trim()),
+        _ if input_str.starts_with("cat ") => commands::cat(input_str[4..].trim()),
+        _ if input_str.starts_with("cd ") => commands::cd(input_str[3..].trim()),
+        _ if input_str.starts_with("color ") => commands::color(input_str[6..].trim()),
+        _ if input_str.starts_with("cp ") => commands::cp(input_str[3..].trim()),
         _ if input_str.starts_with("delay ") => commands::delay(input_str[6..].trim()),
+        _ if input_str.starts_with("disks ") => commands::disks(input_str[6..].trim()),
         _ if input_str.starts_with("echo ") => commands::echo(&input_str[5..].trim()),
-        _ if input_str.starts_with("rm ") => system_call(2, &input_str[3..].trim().as_bytes()),
-        _ if input_str.starts_with("touch ") => system_call(3, &input_str[6..].trim().as_bytes()),
+        _ if input_str.starts_with("font ") => commands::font(input_str[5..].trim()),
+        _ if input_str.starts_with("kbdrate ") => commands::kbdrate(input_str[8..].trim()),
+        _ if input_str.starts_with("keymap ") => commands::keymap(input_str[7..].trim()),
+        _ if input_str.starts_with("ls ") => commands::ls(input_str[3..].trim()),
+        _ if input_str.starts_with("mkdir ") => commands::mkdir(input_str[6..].trim()),
+        _ if input_str.starts_with("mkfs.fat ") => commands::mkfs_fat(input_str[9..].trim()),
+        _ if input_str.starts_with("mode ") => commands::mode(input_str[5..].trim()),
+        _ if input_str.starts_with("palette ") => commands::palette(input_str[8..].trim()),
+        _ if input_str.starts_with("mount ") => commands::mount(input_str[6..].trim()),
         _ if input_str.starts_with("mv ") => commands::mv(input_str[3..].trim()),
+        _ if input_str.starts_with("rm ") => commands::rm(input_str[3..].trim()),
+        _ if input_str.starts_with("rmdir ") => commands::rmdir(input_str[6..].trim()),
+        _ if input_str.starts_with("screenshot ") => commands::screenshot(input_str[11..].trim()),
+        _ if input_str.starts_with("status ") => commands::status(input_str[7..].trim()),
+        _ if input_str.starts_with("theme ") => commands::theme(input_str[6..].trim()),
+        _ if input_str.starts_with("touch ") => commands::touch(input_str[6..].trim()),
+        _ if input_str.starts_with("umount ") => commands::umount(input_str[7..].trim()),
+        _ if input_str.starts_with("var ") => commands::var(input_str[4..].trim()),
+        _ if input_str.starts_with("vbe ") => commands::vbe(input_str[4..].trim()),
+        _ if input_str.starts_with("write ") => commands::write(input_str[6..].trim()),
         _ => {
             unsafe {
                 ERROR = true;
@@ -0,0 +1,123 @@
+use core::arch::global_asm;
+use core::ptr::addr_of_mut;
+use crate::{gfx, shell, status, vbe};
+use crate::vga_buffer;
+
+/// The number of virtual consoles, switched with Alt+F1 to Alt+F6.
+pub const CONSOLES: usize = 6;
+
+/// Stack size of the shells started on the consoles other than the first one, which keeps
+/// running on the boot stack.
+const STACK_SIZE: usize = 128 * 1024;
+
+#[repr(align(16))]
+struct Stack([u8; STACK_SIZE]);
+
+static mut STACKS: [Stack; CONSOLES - 1] = [const { Stack([0; STACK_SIZE]) }; CONSOLES - 1];
+/// Saved stack pointers of the tasks that are not running.
+static mut STACK_POINTERS: [u64; CONSOLES] = [0; CONSOLES];
+static mut STARTED: [bool; CONSOLES] = [true, false, false, false, false, false];
+static mut CURRENT: usize = 0;
+static mut VISIBLE: usize = 0;
+
+// Saves the callee-saved registers on the current stack, stores the stack pointer through
+// `rdi`, switches to the stack in `rsi` and restores the registers saved there. Returning
+// then resumes the other task where it last called `switch_stack`.
+global_asm!(
+    ".global console_switch_stack",
+    "console_switch_stack:",
+    "push rbx",
+    "push rbp",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "ret",
+);
+
+extern "C" {
+    fn console_switch_stack(old: *mut u64, new: u64);
+}
+
+/// Returns the console of the running task, which is where its output goes.
+pub fn current() -> usize {
+    unsafe { CURRENT }
+}
+
+/// Returns the console shown on the screen, which is the one that receives input.
+pub fn visible() -> usize {
+    unsafe { VISIBLE }
+}
+
+extern "C" fn task_entry() -> ! {
+    shell::shell();
+    loop {
+        yield_now();
+    }
+}
+
+/// Prepares the stack of a console's shell so that the first switch to it enters
+/// `task_entry` with the stack aligned as after a call.
+fn start(console: usize) {
+    unsafe {
+        let stack = &mut (*addr_of_mut!(STACKS))[console - 1];
+        let top = stack.0.as_mut_ptr().add(STACK_SIZE) as *mut u64;
+        // fake return address of `task_entry`, then the address `switch_stack` returns to,
+        // then the six callee-saved registers
+        top.sub(1).write(0);
+        top.sub(2).write(task_entry as extern "C" fn() -> ! as usize as u64);
+        for slot in 3..=8 {
+            top.sub(slot).write(0);
+        }
+        STACK_POINTERS[console] = top.sub(8) as u64;
+        STARTED[console] = true;
+    }
+}
+
+/// Brings a console to the screen, starting a shell on it the first time it is shown.
+pub fn show(console: usize) {
+    let previous = visible();
+    // the other consoles' screens cannot be swapped in while graphics are shown
+    if console >= CONSOLES || console == previous || gfx::active() || vbe::active() {
+        return;
+    }
+    vga_buffer::switch_console(previous, console);
+    unsafe {
+        VISIBLE = console;
+        if !STARTED[console] {
+            start(console);
+        }
+    }
+    status::refresh();
+}
+
+/// Lets the next started console's task run until it yields back.
+///
+/// Consoles are scheduled cooperatively: tasks yield while waiting for input in
+/// `keyboard_buffer::read_char` and whenever they call `keyboard_buffer::poll`. Nothing may be
+/// locked across a call to this function.
+pub fn yield_now() {
+    unsafe {
+        let current = CURRENT;
+        let mut next = current;
+        loop {
+            next = (next + 1) % CONSOLES;
+            if next == current {
+                return;
+            }
+            if STARTED[next] {
+                break;
+            }
+        }
+        CURRENT = next;
+        console_switch_stack(addr_of_mut!(STACK_POINTERS[current]), STACK_POINTERS[next]);
+    }
+}
@@ -0,0 +1,53 @@
+/// Glyph shown for characters that have no code page 437 equivalent.
+pub const REPLACEMENT: u8 = 0xFE;
+
+/// Unicode characters for the CP437 glyphs in 0x00..=0x1F, which VGA text mode shows as
+/// symbols rather than control codes.
+const LOW: [char; 32] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Unicode characters for CP437 0x80..=0xFF.
+const HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Returns the CP437 code point that displays `character`, or `REPLACEMENT` if there is none.
+///
+/// Printable ASCII maps to itself. A few look-alikes (Greek beta and mu, the Ohm sign) are
+/// folded onto the glyph CP437 provides for them.
+pub fn encode(character: char) -> u8 {
+    match character {
+        ' '..='~' => return character as u8,
+        '⌂' => return 0x7F,
+        '\u{3b2}' => return 0xE1,
+        '\u{3bc}' => return 0xE6,
+        '\u{2126}' => return 0xEA,
+        _ => (),
+    }
+    if let Some(index) = HIGH.iter().position(|&c| c == character) {
+        return 0x80 + index as u8;
+    }
+    match LOW.iter().skip(1).position(|&c| c == character) {
+        Some(index) => 1 + index as u8,
+        None => REPLACEMENT,
+    }
+}
+
+/// Returns the Unicode character for a CP437 code point, as read back from the screen.
+pub fn decode(byte: u8) -> char {
+    match byte {
+        0x00..=0x1F => LOW[byte as usize],
+        0x7F => '⌂',
+        0x80..=0xFF => HIGH[byte as usize - 0x80],
+        _ => byte as char,
+    }
+}
@@ -0,0 +1,451 @@
+use spin::Mutex;
+use crate::ata::{self, SECTOR_SIZE};
+use crate::fs::{FileSystem, Stat};
+
+const MAGIC: u16 = 0xEF53;
+/// The superblock is always 1024 bytes into the filesystem, whatever the block size.
+const SUPERBLOCK_OFFSET: u64 = 1024;
+/// Blocks of 1, 2 and 4 KiB are supported.
+const MAX_BLOCK_SIZE: usize = 4096;
+const DESCRIPTOR_SIZE: usize = 32;
+/// Revision 0 filesystems have fixed 128-byte inodes.
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+const ROOT: usize = 2;
+
+/// Directory entries carry a file type byte instead of a 16-bit name length.
+const FEATURE_FILETYPE: u32 = 0x0002;
+/// Group descriptors may place their tables in another group.
+const FEATURE_FLEX_BG: u32 = 0x0200;
+const SUPPORTED_FEATURES: u32 = FEATURE_FILETYPE | FEATURE_FLEX_BG;
+
+const MODE_TYPE: u16 = 0xF000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_REGULAR: u16 = 0x8000;
+const MODE_SYMLINK: u16 = 0xA000;
+
+/// Block pointers in an inode: 12 direct, then single, double and triple indirect.
+const DIRECT_BLOCKS: u64 = 12;
+/// Symbolic links followed while resolving a single name.
+const SYMLINK_DEPTH: usize = 8;
+const SYMLINK_LENGTH: usize = 256;
+const NAME_LENGTH: usize = 255;
+
+/// The fields of an on-disk inode used for reading.
+#[derive(Clone, Copy)]
+struct Inode {
+    mode: u16,
+    size: u64,
+    /// Allocated 512-byte sectors, including the extended attribute block.
+    sectors: u32,
+    attribute_block: u32,
+    blocks: [u32; 15],
+}
+
+impl Inode {
+    fn kind(&self) -> u16 {
+        self.mode & MODE_TYPE
+    }
+
+    fn stat(&self) -> Stat {
+        Stat { directory: self.kind() == MODE_DIRECTORY, size: self.size as usize }
+    }
+}
+
+/// A recently read block.
+struct Cached {
+    block: Option<u32>,
+    data: [u8; MAX_BLOCK_SIZE],
+}
+
+struct Volume {
+    mounted: bool,
+    disk: usize,
+    start: u64,
+    block_size: usize,
+    blocks: u32,
+    free_blocks: u32,
+    inodes: u32,
+    inodes_per_group: u32,
+    inode_size: usize,
+    /// The block holding the group descriptor table, right after the superblock.
+    descriptors: u32,
+    features: u32,
+    /// Two blocks, so that walking a directory does not evict it while its inodes are read.
+    cache: [Cached; 2],
+    /// The cache slot used least recently.
+    oldest: usize,
+}
+
+/// A read-only ext2 filesystem on an ATA disk or partition.
+pub struct Ext2 {
+    volume: Mutex<Volume>,
+}
+
+/// Filesystems handed out by `mount ext2 [path] [device]`.
+static VOLUMES: [Ext2; 2] = [const { Ext2::new() }; 2];
+
+/// Mounts the ext2 filesystem on a device such as hda or hda1.
+pub fn instance(device: &str) -> Result<&'static dyn FileSystem, &'static str> {
+    let (disk, start, sectors) = ata::device(device)?;
+    if VOLUMES.iter().any(|ext2| {
+        let volume = ext2.volume.lock();
+        volume.mounted && volume.disk == disk && volume.start == start
+    }) {
+        return Err("Device is already mounted");
+    }
+    let ext2 = VOLUMES.iter().find(|ext2| !ext2.volume.lock().mounted).ok_or("All ext2 volumes are mounted")?;
+    ext2.volume.lock().open(disk, start, sectors)?;
+    Ok(ext2)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+impl Ext2 {
+    const fn new() -> Self {
+        Ext2 {
+            volume: Mutex::new(Volume {
+                mounted: false,
+                disk: 0,
+                start: 0,
+                block_size: 1024,
+                blocks: 0,
+                free_blocks: 0,
+                inodes: 0,
+                inodes_per_group: 0,
+                inode_size: GOOD_OLD_INODE_SIZE,
+                descriptors: 0,
+                features: 0,
+                cache: [const { Cached { block: None, data: [0; MAX_BLOCK_SIZE] } }; 2],
+                oldest: 0,
+            }),
+        }
+    }
+}
+
+impl Volume {
+    /// Reads the superblock and checks that the filesystem can be read.
+    fn open(&mut self, disk: usize, start: u64, sectors: u64) -> Result<(), &'static str> {
+        self.disk = disk;
+        self.start = start;
+        self.cache.iter_mut().for_each(|cached| cached.block = None);
+        let mut superblock = [0u8; 2 * SECTOR_SIZE];
+        ata::read(disk, start + SUPERBLOCK_OFFSET / SECTOR_SIZE as u64, &mut superblock)?;
+        if u16_at(&superblock, 56) != MAGIC {
+            return Err("No ext2 filesystem found");
+        }
+        let log_block_size = u32_at(&superblock, 24);
+        if log_block_size > 2 {
+            return Err("Unsupported ext2 block size");
+        }
+        self.block_size = 1024 << log_block_size;
+        self.blocks = u32_at(&superblock, 4);
+        self.free_blocks = u32_at(&superblock, 12);
+        self.inodes = u32_at(&superblock, 0);
+        self.inodes_per_group = u32_at(&superblock, 40);
+        let first_data_block = u32_at(&superblock, 20);
+        self.descriptors = first_data_block + 1;
+        let revision = u32_at(&superblock, 76);
+        self.inode_size = if revision == 0 { GOOD_OLD_INODE_SIZE } else { u16_at(&superblock, 88) as usize };
+        self.features = if revision == 0 { 0 } else { u32_at(&superblock, 96) };
+        if self.features & !SUPPORTED_FEATURES != 0 {
+            return Err("Unsupported ext2 features");
+        }
+        if self.inodes_per_group == 0
+            || self.inode_size < GOOD_OLD_INODE_SIZE
+            || !self.inode_size.is_power_of_two()
+            || self.inode_size > self.block_size
+        {
+            return Err("No ext2 filesystem found");
+        }
+        if self.blocks as u64 * (self.block_size / SECTOR_SIZE) as u64 > sectors {
+            return Err("The filesystem is larger than the device");
+        }
+        if self.free_blocks > self.blocks {
+            return Err("Corrupt filesystem");
+        }
+        self.oldest = 0;
+        self.mounted = true;
+        Ok(())
+    }
+
+    /// Returns the contents of a block, reading it unless it is cached.
+    fn block(&mut self, block: u32) -> Result<&[u8], &'static str> {
+        if block == 0 || block >= self.blocks {
+            return Err("Corrupt filesystem");
+        }
+        let block_size = self.block_size;
+        let slot = match self.cache.iter().position(|cached| cached.block == Some(block)) {
+            Some(slot) => slot,
+            None => {
+                let slot = self.oldest;
+                let cached = &mut self.cache[slot];
+                cached.block = None;
+                let sectors_per_block = (block_size / SECTOR_SIZE) as u64;
+                ata::read(self.disk, self.start + block as u64 * sectors_per_block, &mut cached.data[..block_size])?;
+                cached.block = Some(block);
+                slot
+            }
+        };
+        self.oldest = 1 - slot;
+        Ok(&self.cache[slot].data[..block_size])
+    }
+
+    fn inode(&mut self, inode: usize) -> Result<Inode, &'static str> {
+        if inode == 0 || inode > self.inodes as usize {
+            return Err("Stale file handle");
+        }
+        let group = (inode - 1) / self.inodes_per_group as usize;
+        let index = (inode - 1) % self.inodes_per_group as usize;
+        let descriptors_per_block = self.block_size / DESCRIPTOR_SIZE;
+        let descriptor_block = self.descriptors + (group / descriptors_per_block) as u32;
+        let table = u32_at(self.block(descriptor_block)?, group % descriptors_per_block * DESCRIPTOR_SIZE + 8);
+        let inodes_per_block = self.block_size / self.inode_size;
+        let offset = index % inodes_per_block * self.inode_size;
+        let raw = &self.block(table + (index / inodes_per_block) as u32)?[offset..offset + GOOD_OLD_INODE_SIZE];
+        let mode = u16_at(raw, 0);
+        let mut size = u32_at(raw, 4) as u64;
+        // for regular files this field holds the upper half of the size
+        if mode & MODE_TYPE == MODE_REGULAR {
+            size |= (u32_at(raw, 108) as u64) << 32;
+        }
+        let mut blocks = [0; 15];
+        for (index, block) in blocks.iter_mut().enumerate() {
+            *block = u32_at(raw, 40 + index * 4);
+        }
+        Ok(Inode { mode, size, sectors: u32_at(raw, 28), attribute_block: u32_at(raw, 104), blocks })
+    }
+
+    /// Returns the block holding the `index`th block of a file, or 0 for a hole.
+    fn map(&mut self, inode: &Inode, index: u64) -> Result<u32, &'static str> {
+        if index < DIRECT_BLOCKS {
+            return Ok(inode.blocks[index as usize]);
+        }
+        let per_block = (self.block_size / 4) as u64;
+        let mut index = index - DIRECT_BLOCKS;
+        // find how many levels of indirect blocks lead to the block
+        let mut levels = 1;
+        let mut span = per_block;
+        while index >= span {
+            index -= span;
+            levels += 1;
+            span *= per_block;
+            if levels > 3 {
+                return Err("File too large");
+            }
+        }
+        let mut block = inode.blocks[DIRECT_BLOCKS as usize + levels - 1];
+        for _ in 0..levels {
+            if block == 0 {
+                return Ok(0);
+            }
+            span /= per_block;
+            block = u32_at(self.block(block)?, (index / span % per_block) as usize * 4);
+        }
+        Ok(block)
+    }
+
+    /// Reads from a file at `offset`; holes read as zero.
+    fn read(&mut self, inode: &Inode, offset: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let size = inode.size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let length = buffer.len().min(size - offset);
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let within = position % self.block_size;
+            let count = (self.block_size - within).min(length - done);
+            let block = self.map(inode, (position / self.block_size) as u64)?;
+            if block == 0 {
+                buffer[done..done + count].fill(0);
+            } else {
+                buffer[done..done + count].copy_from_slice(&self.block(block)?[within..within + count]);
+            }
+            done += count;
+        }
+        Ok(length)
+    }
+
+    /// Calls `f` with the name and inode of each entry of a directory, except `.` and `..`,
+    /// until it returns `true`, and returns that inode.
+    fn scan(
+        &mut self,
+        directory: &Inode,
+        f: &mut dyn FnMut(&mut Volume, &str, usize) -> Result<bool, &'static str>,
+    ) -> Result<Option<usize>, &'static str> {
+        let mut name = [0u8; NAME_LENGTH];
+        let mut position = 0;
+        while position < directory.size as usize {
+            let within = position % self.block_size;
+            let block = self.map(directory, (position / self.block_size) as u64)?;
+            if block == 0 {
+                position += self.block_size - within;
+                continue;
+            }
+            let filetype = self.features & FEATURE_FILETYPE != 0;
+            let data = self.block(block)?;
+            let entry = &data[within..];
+            if entry.len() < 8 {
+                return Err("Corrupt directory");
+            }
+            let inode = u32_at(entry, 0) as usize;
+            let record_length = u16_at(entry, 4) as usize;
+            let name_length = if filetype { entry[6] as usize } else { u16_at(entry, 6) as usize };
+            if record_length < 8 || record_length > entry.len() || name_length > (record_length - 8).min(NAME_LENGTH) {
+                return Err("Corrupt directory");
+            }
+            name[..name_length].copy_from_slice(&entry[8..8 + name_length]);
+            position += record_length;
+            let name = core::str::from_utf8(&name[..name_length]).unwrap_or("");
+            if inode == 0 || name == "." || name == ".." {
+                continue;
+            }
+            if f(self, name, inode)? {
+                return Ok(Some(inode));
+            }
+        }
+        Ok(None)
+    }
+
+    fn directory(&mut self, inode: usize) -> Result<Inode, &'static str> {
+        let directory = self.inode(inode)?;
+        if directory.kind() != MODE_DIRECTORY {
+            return Err("Not a directory");
+        }
+        Ok(directory)
+    }
+
+    /// Finds an entry of a directory, including `.` and `..`, without following symbolic links.
+    fn find(&mut self, directory: usize, name: &str) -> Result<usize, &'static str> {
+        let inode = self.directory(directory)?;
+        if name == "." {
+            return Ok(directory);
+        }
+        if name == ".." {
+            // `..` is the second entry of every directory
+            let block = self.map(&inode, 0)?;
+            let data = self.block(block)?;
+            let first_length = u16_at(data, 4) as usize;
+            if first_length + 8 > data.len() {
+                return Err("Corrupt directory");
+            }
+            return Ok(u32_at(data, first_length) as usize);
+        }
+        self.scan(&inode, &mut |_, other, _| Ok(other == name))?.ok_or("No such file or directory")
+    }
+
+    /// Replaces a symbolic link by the file it points to, relative to `directory`. Absolute
+    /// targets start at the root of this filesystem.
+    fn follow(&mut self, directory: usize, inode: usize, depth: usize) -> Result<usize, &'static str> {
+        let link = self.inode(inode)?;
+        if link.kind() != MODE_SYMLINK {
+            return Ok(inode);
+        }
+        if depth == SYMLINK_DEPTH {
+            return Err("Too many levels of symbolic links");
+        }
+        let length = link.size as usize;
+        if length > SYMLINK_LENGTH {
+            return Err("Symbolic link too long");
+        }
+        let mut target = [0u8; SYMLINK_LENGTH];
+        let attribute_sectors = if link.attribute_block != 0 { (self.block_size / SECTOR_SIZE) as u32 } else { 0 };
+        if link.sectors == attribute_sectors {
+            // short targets are stored in place of the block pointers
+            for (index, block) in link.blocks.iter().enumerate() {
+                target[index * 4..index * 4 + 4].copy_from_slice(&block.to_le_bytes());
+            }
+        } else {
+            self.read(&link, 0, &mut target[..length])?;
+        }
+        let target = core::str::from_utf8(&target[..length]).map_err(|_| "Invalid symbolic link")?;
+        let mut current = if target.starts_with('/') { ROOT } else { directory };
+        for name in target.split('/').filter(|name| !name.is_empty()) {
+            let next = self.find(current, name)?;
+            current = self.follow(current, next, depth + 1)?;
+        }
+        Ok(current)
+    }
+}
+
+impl FileSystem for Ext2 {
+    fn kind(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&self) -> usize {
+        ROOT
+    }
+
+    fn lookup(&self, directory: usize, name: &str) -> Result<usize, &'static str> {
+        let mut volume = self.volume.lock();
+        let inode = volume.find(directory, name)?;
+        volume.follow(directory, inode, 0)
+    }
+
+    fn create(&self, _directory: usize, _name: &str, _is_directory: bool) -> Result<usize, &'static str> {
+        Err("Read-only filesystem")
+    }
+
+    fn read(&self, inode: usize, offset: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let mut volume = self.volume.lock();
+        let file = volume.inode(inode)?;
+        match file.kind() {
+            MODE_REGULAR => volume.read(&file, offset, buffer),
+            MODE_DIRECTORY => Err("Is a directory"),
+            _ => Err("Not a regular file"),
+        }
+    }
+
+    fn write(&self, _inode: usize, _offset: usize, _data: &[u8]) -> Result<usize, &'static str> {
+        Err("Read-only filesystem")
+    }
+
+    fn truncate(&self, _inode: usize, _size: usize) -> Result<(), &'static str> {
+        Err("Read-only filesystem")
+    }
+
+    fn stat(&self, inode: usize) -> Result<Stat, &'static str> {
+        Ok(self.volume.lock().inode(inode)?.stat())
+    }
+
+    fn readdir(&self, directory: usize, f: &mut dyn FnMut(&str, Stat)) -> Result<(), &'static str> {
+        let mut volume = self.volume.lock();
+        let inode = volume.directory(directory)?;
+        volume.scan(&inode, &mut |volume, name, inode| {
+            // links show what they point to, or themselves when broken
+            let target = volume.follow(directory, inode, 0).unwrap_or(inode);
+            f(name, volume.inode(target)?.stat());
+            Ok(false)
+        })?;
+        Ok(())
+    }
+
+    fn unlink(&self, _directory: usize, _name: &str) -> Result<(), &'static str> {
+        Err("Read-only filesystem")
+    }
+
+    fn rename(&self, _directory: usize, _name: &str, _target: usize, _new_name: &str) -> Result<(), &'static str> {
+        Err("Read-only filesystem")
+    }
+
+    fn usage(&self) -> (usize, usize) {
+        let volume = self.volume.lock();
+        let size = volume.blocks as usize * volume.block_size;
+        (size, size - volume.free_blocks as usize * volume.block_size)
+    }
+
+    fn unmount(&self) {
+        let mut volume = self.volume.lock();
+        volume.mounted = false;
+        volume.cache.iter_mut().for_each(|cached| cached.block = None);
+    }
+}
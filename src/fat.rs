@@ -0,0 +1,1187 @@
+use spin::Mutex;
+use crate::ata::{self, SECTOR_SIZE};
+use crate::fs::{self, FileSystem, Stat};
+
+const ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / ENTRY_SIZE;
+
+const ATTRIBUTE_VOLUME: u8 = 0x08;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+const ATTRIBUTE_ARCHIVE: u8 = 0x20;
+/// Read-only, hidden, system and volume together mark a long name entry.
+const ATTRIBUTE_LONG_NAME: u8 = 0x0F;
+
+/// First name byte of a deleted entry; 0 marks the end of the directory.
+const DELETED: u8 = 0xE5;
+/// Set in the sequence number of the last (first stored) long name entry.
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// Positions of the 13 UCS-2 characters in a long name entry.
+const LONG_NAME_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Long names are at most 255 UCS-2 characters, 20 entries.
+const LONG_NAME_LENGTH: usize = 255;
+const LONG_NAME_ENTRIES: usize = 20;
+
+/// Flags in the reserved byte that Windows NT uses for lowercase short names.
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXTENSION: u8 = 0x10;
+
+/// 1980-01-01, the earliest date a directory entry can hold.
+const DEFAULT_DATE: u16 = 0x0021;
+const MEDIA: u8 = 0xF8;
+
+/// The root directory. Other inodes are the sector and index of their short directory entry,
+/// which is never in sector 0.
+const ROOT: usize = 0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Fat12 => "fat12",
+            Kind::Fat16 => "fat16",
+            Kind::Fat32 => "fat32",
+        }
+    }
+
+    /// The value marking the end of a cluster chain.
+    fn end_of_chain(self) -> u32 {
+        match self {
+            Kind::Fat12 => 0xFFF,
+            Kind::Fat16 => 0xFFFF,
+            Kind::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    /// The size of a FAT entry in bits.
+    fn entry_bits(self) -> u64 {
+        match self {
+            Kind::Fat12 => 12,
+            Kind::Fat16 => 16,
+            Kind::Fat32 => 32,
+        }
+    }
+}
+
+/// A directory: the fixed root directory region of FAT12 and FAT16, or a cluster chain.
+#[derive(Clone, Copy)]
+enum Directory {
+    Root,
+    Chain(u32),
+}
+
+/// A directory entry with its long name, if any.
+struct Entry {
+    name: [u8; LONG_NAME_ENTRIES * 13 * 3],
+    name_length: usize,
+    short: [u8; 11],
+    attributes: u8,
+    cluster: u32,
+    size: u32,
+    /// Slot of the first long name entry, or of the short entry without a long name.
+    first_slot: usize,
+    slot: usize,
+}
+
+impl Entry {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_length]).unwrap_or("")
+    }
+
+    fn directory(&self) -> bool {
+        self.attributes & ATTRIBUTE_DIRECTORY != 0
+    }
+}
+
+/// Sectors are numbered from the start of the partition.
+struct Volume {
+    mounted: bool,
+    disk: usize,
+    start: u64,
+    sectors: u64,
+    kind: Kind,
+    sectors_per_cluster: u64,
+    fat_start: u64,
+    fat_sectors: u64,
+    fats: u64,
+    root_start: u64,
+    root_sectors: u64,
+    data_start: u64,
+    /// Data clusters are numbered from 2 to `clusters + 1`.
+    clusters: u32,
+    root_cluster: u32,
+    /// Where the search for a free cluster starts.
+    next_free: u32,
+    cache: [u8; SECTOR_SIZE],
+    cached: Option<u64>,
+}
+
+/// A FAT12, FAT16 or FAT32 filesystem on an ATA disk or partition.
+pub struct Fat {
+    volume: Mutex<Volume>,
+}
+
+/// Filesystems handed out by `mount fat [path] [device]`.
+static VOLUMES: [Fat; 2] = [const { Fat::new() }; 2];
+
+/// Mounts the FAT filesystem on a device such as hda or hda1.
+pub fn instance(device: &str) -> Result<&'static dyn FileSystem, &'static str> {
+    let (disk, start, sectors) = ata::device(device)?;
+    if VOLUMES.iter().any(|fat| {
+        let volume = fat.volume.lock();
+        volume.mounted && volume.disk == disk && volume.start == start
+    }) {
+        return Err("Device is already mounted");
+    }
+    let fat = VOLUMES.iter().find(|fat| !fat.volume.lock().mounted).ok_or("All FAT volumes are mounted")?;
+    fat.volume.lock().open(disk, start, sectors)?;
+    Ok(fat)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn checksum(short: &[u8; 11]) -> u8 {
+    short.iter().fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+fn valid_short_character(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&byte)
+}
+
+/// Formats a short name as it is shown when there is no long name.
+fn short_display(short: &[u8; 11], flags: u8, output: &mut [u8]) -> usize {
+    let mut length = 0;
+    let mut put = |bytes: &[u8], lowercase: bool| {
+        for &byte in bytes.iter().filter(|&&byte| byte != b' ') {
+            output[length] = if lowercase { byte.to_ascii_lowercase() } else { byte };
+            length += 1;
+        }
+    };
+    put(&short[..8], flags & LOWERCASE_BASE != 0);
+    if short[8] != b' ' {
+        put(b".", false);
+        put(&short[8..], flags & LOWERCASE_EXTENSION != 0);
+    }
+    // 0x05 stands for a first byte of 0xE5
+    if length > 0 && output[0] == 0x05 {
+        output[0] = DELETED;
+    }
+    length
+}
+
+/// Returns the short name and case flags for a name that fits 8.3 exactly.
+fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, extension) = match name.rfind('.') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 || name.ends_with('.') {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    let mut flags = 0;
+    for (part, field, flag) in [(base, 0, LOWERCASE_BASE), (extension, 8, LOWERCASE_EXTENSION)] {
+        if !part.bytes().all(valid_short_character) {
+            return None;
+        }
+        let lower = part.bytes().any(|byte| byte.is_ascii_lowercase());
+        let upper = part.bytes().any(|byte| byte.is_ascii_uppercase());
+        if lower && upper {
+            return None;
+        }
+        if lower {
+            flags |= flag;
+        }
+        for (index, byte) in part.bytes().enumerate() {
+            short[field + index] = byte.to_ascii_uppercase();
+        }
+    }
+    Some((short, flags))
+}
+
+/// Builds the short name `BASIS~N.EXT` for a long name. Past the fourth attempt the basis
+/// becomes two characters and a hash of the name, as Windows does, so that a directory full
+/// of similar names does not need a search through every number.
+fn numbered_short_name(name: &str, number: u32) -> [u8; 11] {
+    let (base, extension) = match name.rfind('.') {
+        Some(index) if index > 0 => (&name[..index], &name[index + 1..]),
+        _ => (name, ""),
+    };
+    let clean = |byte: u8| if valid_short_character(byte) { byte.to_ascii_uppercase() } else { b'_' };
+    let mut basis = [0u8; 8];
+    let mut basis_length = 0;
+    for byte in base.bytes().filter(|&byte| byte != b' ' && byte != b'.').take(8) {
+        basis[basis_length] = clean(byte);
+        basis_length += 1;
+    }
+    let mut number = number;
+    if number > 4 {
+        let hash = name.bytes().fold(0u16, |hash, byte| hash.rotate_left(5) ^ byte as u16);
+        basis_length = basis_length.min(2);
+        for shift in [12, 8, 4, 0] {
+            basis[basis_length] = b"0123456789ABCDEF"[(hash >> shift) as usize & 0xF];
+            basis_length += 1;
+        }
+        number -= 4;
+    }
+    let mut suffix = [0u8; 8];
+    let mut digits = 0;
+    while number > 0 || digits == 0 {
+        suffix[7 - digits] = b'0' + (number % 10) as u8;
+        number /= 10;
+        digits += 1;
+    }
+    suffix[7 - digits] = b'~';
+    let tail = &suffix[7 - digits..];
+    let kept = basis_length.min(8 - tail.len());
+    let mut short = [b' '; 11];
+    short[..kept].copy_from_slice(&basis[..kept]);
+    short[kept..kept + tail.len()].copy_from_slice(tail);
+    for (index, byte) in extension.bytes().filter(|&byte| byte != b' ').take(3).enumerate() {
+        short[8 + index] = clean(byte);
+    }
+    short
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.encode_utf16().count() <= LONG_NAME_LENGTH
+        && !name.chars().any(|character| character < ' ' || "\\/:*?\"<>|".contains(character))
+}
+
+impl Fat {
+    const fn new() -> Self {
+        Fat {
+            volume: Mutex::new(Volume {
+                mounted: false,
+                disk: 0,
+                start: 0,
+                sectors: 0,
+                kind: Kind::Fat16,
+                sectors_per_cluster: 1,
+                fat_start: 0,
+                fat_sectors: 0,
+                fats: 0,
+                root_start: 0,
+                root_sectors: 0,
+                data_start: 0,
+                clusters: 0,
+                root_cluster: 0,
+                next_free: 2,
+                cache: [0; SECTOR_SIZE],
+                cached: None,
+            }),
+        }
+    }
+}
+
+impl Volume {
+    /// Reads the BIOS parameter block and checks that it describes a usable filesystem.
+    fn open(&mut self, disk: usize, start: u64, sectors: u64) -> Result<(), &'static str> {
+        self.disk = disk;
+        self.start = start;
+        self.sectors = sectors;
+        self.cached = None;
+        let boot = self.read_sector(0)?;
+        if boot[510..] != [0x55, 0xAA] {
+            return Err("No FAT filesystem found");
+        }
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = u16_at(&boot, 14) as u64;
+        let fats = boot[16] as u64;
+        let root_entries = u16_at(&boot, 17) as u64;
+        let total = match u16_at(&boot, 19) {
+            0 => u32_at(&boot, 32) as u64,
+            total => total as u64,
+        };
+        let fat_sectors = match u16_at(&boot, 22) {
+            0 => u32_at(&boot, 36) as u64,
+            size => size as u64,
+        };
+        if u16_at(&boot, 11) as usize != SECTOR_SIZE
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || fats == 0
+            || fat_sectors == 0
+        {
+            return Err("No FAT filesystem found");
+        }
+        if total > sectors {
+            return Err("The filesystem is larger than the device");
+        }
+        self.sectors_per_cluster = sectors_per_cluster;
+        self.fat_start = reserved;
+        self.fat_sectors = fat_sectors;
+        self.fats = fats;
+        self.root_start = reserved + fats * fat_sectors;
+        self.root_sectors = (root_entries * ENTRY_SIZE as u64).div_ceil(SECTOR_SIZE as u64);
+        self.data_start = self.root_start + self.root_sectors;
+        if self.data_start >= total {
+            return Err("No FAT filesystem found");
+        }
+        self.clusters = ((total - self.data_start) / sectors_per_cluster) as u32;
+        // the cluster count alone decides the FAT type
+        self.kind = match self.clusters {
+            0..=4084 => Kind::Fat12,
+            4085..=65524 => Kind::Fat16,
+            _ => Kind::Fat32,
+        };
+        // every cluster, and the two reserved entries, must have an entry within the FAT
+        if fat_sectors * SECTOR_SIZE as u64 * 8 / self.kind.entry_bits() < self.clusters as u64 + 2 {
+            return Err("No FAT filesystem found");
+        }
+        self.root_cluster = if self.kind == Kind::Fat32 { u32_at(&boot, 44) } else { 0 };
+        if self.kind == Kind::Fat32 && !self.valid_cluster(self.root_cluster) {
+            return Err("No FAT filesystem found");
+        }
+        self.next_free = 2;
+        self.mounted = true;
+        Ok(())
+    }
+
+    fn read_sector(&mut self, sector: u64) -> Result<[u8; SECTOR_SIZE], &'static str> {
+        if self.cached != Some(sector) {
+            self.cached = None;
+            ata::read(self.disk, self.start + sector, &mut self.cache)?;
+            self.cached = Some(sector);
+        }
+        Ok(self.cache)
+    }
+
+    fn write_sector(&mut self, sector: u64, data: &[u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        self.cached = None;
+        ata::write(self.disk, self.start + sector, data)?;
+        self.cache = *data;
+        self.cached = Some(sector);
+        Ok(())
+    }
+
+    fn cluster_bytes(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        (2..self.clusters + 2).contains(&cluster)
+    }
+
+    /// Byte offset and length of a cluster's entry in the FAT.
+    fn fat_position(&self, cluster: u32) -> (u64, usize) {
+        match self.kind {
+            Kind::Fat12 => (cluster as u64 + cluster as u64 / 2, 2),
+            Kind::Fat16 => (cluster as u64 * 2, 2),
+            Kind::Fat32 => (cluster as u64 * 4, 4),
+        }
+    }
+
+    /// Reads the raw bytes of a FAT entry, which may cross a sector boundary on FAT12.
+    fn read_fat_bytes(&mut self, cluster: u32) -> Result<u32, &'static str> {
+        let (offset, length) = self.fat_position(cluster);
+        let mut value = 0;
+        for index in 0..length as u64 {
+            let sector = self.read_sector(self.fat_start + (offset + index) / SECTOR_SIZE as u64)?;
+            value |= (sector[((offset + index) % SECTOR_SIZE as u64) as usize] as u32) << (index * 8);
+        }
+        Ok(value)
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, &'static str> {
+        let value = self.read_fat_bytes(cluster)?;
+        Ok(match self.kind {
+            Kind::Fat12 if cluster & 1 == 1 => value >> 4,
+            Kind::Fat12 => value & 0xFFF,
+            Kind::Fat16 => value,
+            Kind::Fat32 => value & 0x0FFF_FFFF,
+        })
+    }
+
+    /// Sets a FAT entry in every copy of the FAT.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), &'static str> {
+        let old = self.read_fat_bytes(cluster)?;
+        let raw = match self.kind {
+            Kind::Fat12 if cluster & 1 == 1 => old & 0x000F | value << 4,
+            Kind::Fat12 => old & 0xF000 | value & 0xFFF,
+            Kind::Fat16 => value,
+            Kind::Fat32 => old & 0xF000_0000 | value & 0x0FFF_FFFF,
+        };
+        let (offset, length) = self.fat_position(cluster);
+        let bytes = raw.to_le_bytes();
+        for copy in 0..self.fats {
+            let base = self.fat_start + copy * self.fat_sectors;
+            let mut index = 0;
+            while index < length {
+                let number = base + (offset + index as u64) / SECTOR_SIZE as u64;
+                let mut sector = self.read_sector(number)?;
+                while index < length && base + (offset + index as u64) / SECTOR_SIZE as u64 == number {
+                    sector[((offset + index as u64) % SECTOR_SIZE as u64) as usize] = bytes[index];
+                    index += 1;
+                }
+                self.write_sector(number, &sector)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster after `cluster` in its chain, or `None` at the end.
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, &'static str> {
+        let next = self.fat_entry(cluster)?;
+        Ok(if self.valid_cluster(next) { Some(next) } else { None })
+    }
+
+    /// Returns the `index`th cluster of a chain, if the chain is that long.
+    fn nth_cluster(&mut self, first: u32, index: usize) -> Result<Option<u32>, &'static str> {
+        if !self.valid_cluster(first) {
+            return Ok(None);
+        }
+        let mut cluster = first;
+        for _ in 0..index {
+            match self.next_cluster(cluster)? {
+                Some(next) => cluster = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(cluster))
+    }
+
+    /// Takes a free cluster, appends it to `previous` if given and zeroes it if asked to.
+    fn allocate(&mut self, previous: Option<u32>, zero: bool) -> Result<u32, &'static str> {
+        let count = self.clusters;
+        let mut found = None;
+        for step in 0..count {
+            let cluster = 2 + (self.next_free - 2 + step) % count;
+            if self.fat_entry(cluster)? == 0 {
+                found = Some(cluster);
+                break;
+            }
+        }
+        let cluster = found.ok_or("No space left")?;
+        self.set_fat_entry(cluster, self.kind.end_of_chain())?;
+        if let Some(previous) = previous {
+            self.set_fat_entry(previous, cluster)?;
+        }
+        self.next_free = cluster;
+        if zero {
+            let first = self.cluster_sector(cluster);
+            for sector in first..first + self.sectors_per_cluster {
+                self.write_sector(sector, &[0; SECTOR_SIZE])?;
+            }
+        }
+        Ok(cluster)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), &'static str> {
+        let mut cluster = if self.valid_cluster(first) { Some(first) } else { None };
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+        }
+        Ok(())
+    }
+
+    fn free_clusters(&mut self) -> Result<u32, &'static str> {
+        let mut free = 0;
+        for cluster in 2..self.clusters + 2 {
+            if self.fat_entry(cluster)? == 0 {
+                free += 1;
+            }
+        }
+        Ok(free)
+    }
+
+    fn root(&self) -> Directory {
+        match self.kind {
+            Kind::Fat32 => Directory::Chain(self.root_cluster),
+            _ => Directory::Root,
+        }
+    }
+
+    /// Returns the sector and index of the short entry for an inode.
+    fn entry_position(inode: usize) -> (u64, usize) {
+        ((inode / ENTRIES_PER_SECTOR) as u64, inode % ENTRIES_PER_SECTOR)
+    }
+
+    fn raw_entry(&mut self, inode: usize) -> Result<[u8; ENTRY_SIZE], &'static str> {
+        let (sector, index) = Self::entry_position(inode);
+        let data = self.read_sector(sector)?;
+        let mut entry = [0; ENTRY_SIZE];
+        entry.copy_from_slice(&data[index * ENTRY_SIZE..][..ENTRY_SIZE]);
+        if entry[0] == 0 || entry[0] == DELETED || entry[11] == ATTRIBUTE_LONG_NAME {
+            return Err("Stale file handle");
+        }
+        Ok(entry)
+    }
+
+    fn write_raw_entry(&mut self, inode: usize, entry: &[u8; ENTRY_SIZE]) -> Result<(), &'static str> {
+        let (sector, index) = Self::entry_position(inode);
+        let mut data = self.read_sector(sector)?;
+        data[index * ENTRY_SIZE..][..ENTRY_SIZE].copy_from_slice(entry);
+        self.write_sector(sector, &data)
+    }
+
+    /// Returns the first cluster, size and attributes of an inode.
+    fn inode(&mut self, inode: usize) -> Result<(u32, u32, u8), &'static str> {
+        if inode == ROOT {
+            let cluster = if self.kind == Kind::Fat32 { self.root_cluster } else { 0 };
+            return Ok((cluster, 0, ATTRIBUTE_DIRECTORY));
+        }
+        let entry = self.raw_entry(inode)?;
+        let cluster = (u16_at(&entry, 20) as u32) << 16 | u16_at(&entry, 26) as u32;
+        Ok((cluster, u32_at(&entry, 28), entry[11]))
+    }
+
+    fn set_inode(&mut self, inode: usize, cluster: u32, size: u32) -> Result<(), &'static str> {
+        let mut entry = self.raw_entry(inode)?;
+        entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&size.to_le_bytes());
+        self.write_raw_entry(inode, &entry)
+    }
+
+    fn directory(&mut self, inode: usize) -> Result<Directory, &'static str> {
+        let (cluster, _, attributes) = self.inode(inode)?;
+        if attributes & ATTRIBUTE_DIRECTORY == 0 {
+            return Err("Not a directory");
+        }
+        Ok(if inode == ROOT { self.root() } else { Directory::Chain(cluster) })
+    }
+
+    /// Returns the `index`th sector of a directory, if the directory is that long.
+    fn directory_sector(&mut self, directory: Directory, index: u64) -> Result<Option<u64>, &'static str> {
+        match directory {
+            Directory::Root => Ok(if index < self.root_sectors { Some(self.root_start + index) } else { None }),
+            Directory::Chain(first) => {
+                let cluster = self.nth_cluster(first, (index / self.sectors_per_cluster) as usize)?;
+                Ok(cluster.map(|cluster| self.cluster_sector(cluster) + index % self.sectors_per_cluster))
+            }
+        }
+    }
+
+    /// Returns the inode of the entry in a slot of a directory.
+    fn slot_inode(&mut self, directory: Directory, slot: usize) -> Result<usize, &'static str> {
+        let sector = self.directory_sector(directory, (slot / ENTRIES_PER_SECTOR) as u64)?.ok_or("Corrupt directory")?;
+        Ok(sector as usize * ENTRIES_PER_SECTOR + slot % ENTRIES_PER_SECTOR)
+    }
+
+    /// Calls `f` with each entry of a directory, except `.` and `..`, until it returns `true`,
+    /// and returns that entry.
+    fn scan(&mut self, directory: Directory, f: &mut dyn FnMut(&Entry) -> bool) -> Result<Option<Entry>, &'static str> {
+        let mut long = [0u16; LONG_NAME_ENTRIES * 13];
+        let mut long_checksum = None;
+        let mut entry = Entry {
+            name: [0; LONG_NAME_ENTRIES * 13 * 3],
+            name_length: 0,
+            short: [0; 11],
+            attributes: 0,
+            cluster: 0,
+            size: 0,
+            first_slot: 0,
+            slot: 0,
+        };
+        let mut index = 0;
+        while let Some(sector) = self.directory_sector(directory, index)? {
+            let data = self.read_sector(sector)?;
+            for (position, raw) in data.chunks_exact(ENTRY_SIZE).enumerate() {
+                let slot = index as usize * ENTRIES_PER_SECTOR + position;
+                if raw[0] == 0 {
+                    return Ok(None);
+                }
+                if raw[0] == DELETED {
+                    long_checksum = None;
+                    continue;
+                }
+                if raw[11] == ATTRIBUTE_LONG_NAME {
+                    let sequence = (raw[0] & 0x1F) as usize;
+                    if raw[0] & LAST_LONG_ENTRY != 0 {
+                        long = [0; LONG_NAME_ENTRIES * 13];
+                        long_checksum = Some(raw[13]);
+                        entry.first_slot = slot;
+                    }
+                    if sequence == 0 || sequence > LONG_NAME_ENTRIES || long_checksum != Some(raw[13]) {
+                        long_checksum = None;
+                        continue;
+                    }
+                    for (offset, &position) in LONG_NAME_OFFSETS.iter().enumerate() {
+                        long[(sequence - 1) * 13 + offset] = u16_at(raw, position);
+                    }
+                    continue;
+                }
+                if raw[11] & ATTRIBUTE_VOLUME != 0 {
+                    long_checksum = None;
+                    continue;
+                }
+                entry.short.copy_from_slice(&raw[..11]);
+                entry.attributes = raw[11];
+                entry.cluster = (u16_at(raw, 20) as u32) << 16 | u16_at(raw, 26) as u32;
+                entry.size = u32_at(raw, 28);
+                entry.slot = slot;
+                if long_checksum == Some(checksum(&entry.short)) {
+                    let length = long.iter().position(|&unit| unit == 0 || unit == 0xFFFF).unwrap_or(long.len());
+                    entry.name_length = 0;
+                    for character in char::decode_utf16(long[..length].iter().copied()) {
+                        let character = character.unwrap_or(char::REPLACEMENT_CHARACTER);
+                        let encoded = character.encode_utf8(&mut entry.name[entry.name_length..]);
+                        entry.name_length += encoded.len();
+                    }
+                } else {
+                    entry.first_slot = slot;
+                    entry.name_length = short_display(&entry.short, raw[12], &mut entry.name);
+                }
+                long_checksum = None;
+                if entry.short[0] == b'.' {
+                    continue;
+                }
+                if f(&entry) {
+                    return Ok(Some(entry));
+                }
+            }
+            index += 1;
+        }
+        Ok(None)
+    }
+
+    /// Finds an entry by its long or short name, ignoring case as FAT does.
+    fn find(&mut self, directory: Directory, name: &str) -> Result<Option<Entry>, &'static str> {
+        self.scan(directory, &mut |entry| {
+            let mut short = [0; 12];
+            let length = short_display(&entry.short, 0, &mut short);
+            entry.name().eq_ignore_ascii_case(name) || short[..length].eq_ignore_ascii_case(name.as_bytes())
+        })
+    }
+
+    /// Finds `count` consecutive free slots, growing a directory cluster chain if needed.
+    fn free_slots(&mut self, directory: Directory, count: usize) -> Result<usize, &'static str> {
+        let mut run = 0;
+        let mut index = 0;
+        let mut last_cluster = None;
+        loop {
+            let sector = match self.directory_sector(directory, index)? {
+                Some(sector) => sector,
+                None => match directory {
+                    Directory::Root => return Err("Directory full"),
+                    Directory::Chain(first) => {
+                        let last = match last_cluster {
+                            Some(last) => last,
+                            None => self.nth_cluster(first, (index / self.sectors_per_cluster - 1) as usize)?.ok_or("Corrupt directory")?,
+                        };
+                        let cluster = self.allocate(Some(last), true)?;
+                        last_cluster = Some(cluster);
+                        self.cluster_sector(cluster)
+                    }
+                },
+            };
+            let data = self.read_sector(sector)?;
+            for (position, raw) in data.chunks_exact(ENTRY_SIZE).enumerate() {
+                if raw[0] == 0 || raw[0] == DELETED {
+                    run += 1;
+                    if run == count {
+                        return Ok(index as usize * ENTRIES_PER_SECTOR + position + 1 - count);
+                    }
+                } else {
+                    run = 0;
+                }
+            }
+            index += 1;
+        }
+    }
+
+    /// Writes a directory entry, with long name entries unless the name fits 8.3, and returns
+    /// its inode.
+    fn insert(&mut self, directory: Directory, name: &str, attributes: u8, cluster: u32, size: u32) -> Result<usize, &'static str> {
+        let (short, flags, long) = match exact_short_name(name) {
+            Some((short, flags)) => (short, flags, false),
+            None => {
+                let mut number = 1;
+                let short = loop {
+                    let short = numbered_short_name(name, number);
+                    if self.scan(directory, &mut |entry| entry.short == short)?.is_none() {
+                        break short;
+                    }
+                    number += 1;
+                    if number > 999_999 {
+                        return Err("Too many similar names");
+                    }
+                };
+                (short, 0, true)
+            }
+        };
+        let mut units = [0u16; LONG_NAME_ENTRIES * 13];
+        let mut length = 0;
+        for unit in name.encode_utf16() {
+            units[length] = unit;
+            length += 1;
+        }
+        let long_entries = if long { length.div_ceil(13) } else { 0 };
+        let slot = self.free_slots(directory, long_entries + 1)?;
+        let sum = checksum(&short);
+        for index in 0..long_entries {
+            let sequence = long_entries - index;
+            let mut raw = [0u8; ENTRY_SIZE];
+            raw[0] = sequence as u8 | if index == 0 { LAST_LONG_ENTRY } else { 0 };
+            raw[11] = ATTRIBUTE_LONG_NAME;
+            raw[13] = sum;
+            for (offset, &position) in LONG_NAME_OFFSETS.iter().enumerate() {
+                let character = (sequence - 1) * 13 + offset;
+                // the name ends with 0 if there is room, then pads with 0xFFFF
+                let unit = match character.cmp(&length) {
+                    core::cmp::Ordering::Less => units[character],
+                    core::cmp::Ordering::Equal => 0,
+                    core::cmp::Ordering::Greater => 0xFFFF,
+                };
+                raw[position..position + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            let inode = self.slot_inode(directory, slot + index)?;
+            self.write_raw_entry(inode, &raw)?;
+        }
+        let mut raw = [0u8; ENTRY_SIZE];
+        raw[..11].copy_from_slice(&short);
+        raw[11] = attributes;
+        raw[12] = flags;
+        for offset in [16, 18, 24] {
+            raw[offset..offset + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        }
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        let inode = self.slot_inode(directory, slot + long_entries)?;
+        self.write_raw_entry(inode, &raw)?;
+        Ok(inode)
+    }
+
+    /// Marks the slots of an entry, long name entries included, as deleted.
+    fn erase(&mut self, directory: Directory, entry: &Entry) -> Result<(), &'static str> {
+        for slot in entry.first_slot..=entry.slot {
+            let inode = self.slot_inode(directory, slot)?;
+            let (sector, index) = Self::entry_position(inode);
+            let mut data = self.read_sector(sector)?;
+            data[index * ENTRY_SIZE] = DELETED;
+            self.write_sector(sector, &data)?;
+        }
+        Ok(())
+    }
+
+    /// The cluster a `..` entry stores for a directory: 0 for the root directory.
+    fn parent_cluster(&self, directory: Directory) -> u32 {
+        match directory {
+            Directory::Chain(cluster) if cluster != self.root_cluster => cluster,
+            _ => 0,
+        }
+    }
+
+    /// Points the `..` entry of a directory at a new parent.
+    fn set_parent(&mut self, cluster: u32, parent: u32) -> Result<(), &'static str> {
+        let sector = self.cluster_sector(cluster);
+        let mut data = self.read_sector(sector)?;
+        let raw = &mut data[ENTRY_SIZE..2 * ENTRY_SIZE];
+        if &raw[..2] != b".." {
+            return Err("Corrupt directory");
+        }
+        raw[20..22].copy_from_slice(&((parent >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(parent as u16).to_le_bytes());
+        self.write_sector(sector, &data)
+    }
+
+    /// Returns `true` if the directory starting at `cluster` is `ancestor` or inside it,
+    /// following `..` entries up to the root.
+    fn within(&mut self, mut cluster: u32, ancestor: u32) -> Result<bool, &'static str> {
+        for _ in 0..self.clusters {
+            if cluster == ancestor {
+                return Ok(true);
+            }
+            if !self.valid_cluster(cluster) || cluster == self.root_cluster {
+                return Ok(false);
+            }
+            let data = self.read_sector(self.cluster_sector(cluster))?;
+            cluster = (u16_at(&data, ENTRY_SIZE + 20) as u32) << 16 | u16_at(&data, ENTRY_SIZE + 26) as u32;
+        }
+        Err("Corrupt directory")
+    }
+
+    fn read(&mut self, inode: usize, offset: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let (first, size, attributes) = self.inode(inode)?;
+        if attributes & ATTRIBUTE_DIRECTORY != 0 {
+            return Err("Is a directory");
+        }
+        let size = size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let length = buffer.len().min(size - offset);
+        let cluster_bytes = self.cluster_bytes();
+        let mut cluster = self.nth_cluster(first, offset / cluster_bytes)?.ok_or("Corrupt file")?;
+        let mut position = offset;
+        let mut done = 0;
+        while done < length {
+            let within = position % cluster_bytes;
+            let sector = self.cluster_sector(cluster) + (within / SECTOR_SIZE) as u64;
+            let data = self.read_sector(sector)?;
+            let start = within % SECTOR_SIZE;
+            let count = (SECTOR_SIZE - start).min(length - done);
+            buffer[done..done + count].copy_from_slice(&data[start..start + count]);
+            done += count;
+            position += count;
+            if done < length && position.is_multiple_of(cluster_bytes) {
+                cluster = self.next_cluster(cluster)?.ok_or("Corrupt file")?;
+            }
+        }
+        Ok(length)
+    }
+
+    fn write(&mut self, inode: usize, offset: usize, data: &[u8]) -> Result<usize, &'static str> {
+        let (_, _, attributes) = self.inode(inode)?;
+        if attributes & ATTRIBUTE_DIRECTORY != 0 {
+            return Err("Is a directory");
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+        if offset + data.len() > u32::MAX as usize {
+            return Err("File too large");
+        }
+        self.write_range(inode, offset, data)?;
+        Ok(data.len())
+    }
+
+    /// Writes `data` at `offset`, filling the gap between the end of the file and `offset`
+    /// with zeros on the same walk along the cluster chain.
+    fn write_range(&mut self, inode: usize, offset: usize, data: &[u8]) -> Result<(), &'static str> {
+        let (mut first, size, _) = self.inode(inode)?;
+        let size = size as usize;
+        let start = offset.min(size);
+        let end = offset + data.len();
+        if start == end {
+            return Ok(());
+        }
+        let cluster_bytes = self.cluster_bytes();
+        if !self.valid_cluster(first) {
+            first = self.allocate(None, false)?;
+            self.set_inode(inode, first, size as u32)?;
+        }
+        // walk to the cluster holding `start`, growing the chain on the way
+        let mut cluster = first;
+        for _ in 0..start / cluster_bytes {
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None => self.allocate(Some(cluster), false)?,
+            };
+        }
+        let mut position = start;
+        while position < end {
+            let within = position % cluster_bytes;
+            let sector = self.cluster_sector(cluster) + (within / SECTOR_SIZE) as u64;
+            let first_byte = within % SECTOR_SIZE;
+            let count = (SECTOR_SIZE - first_byte).min(end - position);
+            let mut buffer = if count == SECTOR_SIZE { [0; SECTOR_SIZE] } else { self.read_sector(sector)? };
+            let target = &mut buffer[first_byte..first_byte + count];
+            let zeros = offset.saturating_sub(position).min(count);
+            target[..zeros].fill(0);
+            if zeros < count {
+                target[zeros..].copy_from_slice(&data[position + zeros - offset..position + count - offset]);
+            }
+            self.write_sector(sector, &buffer)?;
+            position += count;
+            if position < end && position.is_multiple_of(cluster_bytes) {
+                cluster = match self.next_cluster(cluster)? {
+                    Some(next) => next,
+                    None => self.allocate(Some(cluster), false)?,
+                };
+            }
+        }
+        if end > size {
+            self.set_inode(inode, first, end as u32)?;
+        }
+        Ok(())
+    }
+
+    /// Grows a file with zeros or shrinks it and frees the clusters past the end.
+    fn resize(&mut self, inode: usize, size: usize) -> Result<(), &'static str> {
+        let (first, old, attributes) = self.inode(inode)?;
+        if attributes & ATTRIBUTE_DIRECTORY != 0 {
+            return Err("Is a directory");
+        }
+        if size > u32::MAX as usize {
+            return Err("File too large");
+        }
+        let old = old as usize;
+        if size > old {
+            return self.write_range(inode, size, &[]);
+        }
+        let keep = size.div_ceil(self.cluster_bytes());
+        if keep == 0 {
+            self.free_chain(first)?;
+            return self.set_inode(inode, 0, 0);
+        }
+        if let Some(last) = self.nth_cluster(first, keep - 1)? {
+            if let Some(rest) = self.next_cluster(last)? {
+                self.free_chain(rest)?;
+            }
+            self.set_fat_entry(last, self.kind.end_of_chain())?;
+        }
+        self.set_inode(inode, first, size as u32)
+    }
+}
+
+impl FileSystem for Fat {
+    fn kind(&self) -> &'static str {
+        self.volume.lock().kind.name()
+    }
+
+    fn root(&self) -> usize {
+        ROOT
+    }
+
+    fn lookup(&self, directory: usize, name: &str) -> Result<usize, &'static str> {
+        let mut volume = self.volume.lock();
+        let directory = volume.directory(directory)?;
+        let entry = volume.find(directory, name)?.ok_or("No such file or directory")?;
+        volume.slot_inode(directory, entry.slot)
+    }
+
+    fn create(&self, directory: usize, name: &str, is_directory: bool) -> Result<usize, &'static str> {
+        let mut volume = self.volume.lock();
+        let parent = volume.directory(directory)?;
+        if !valid_name(name) {
+            return Err("Invalid name");
+        }
+        if volume.find(parent, name)?.is_some() {
+            return Err("File exists");
+        }
+        if !is_directory {
+            return volume.insert(parent, name, ATTRIBUTE_ARCHIVE, 0, 0);
+        }
+        let cluster = volume.allocate(None, true)?;
+        let parent_cluster = volume.parent_cluster(parent);
+        let mut data = [0u8; SECTOR_SIZE];
+        for (index, &(name, target)) in [(b".          ", cluster), (b"..         ", parent_cluster)].iter().enumerate() {
+            let raw = &mut data[index * ENTRY_SIZE..][..ENTRY_SIZE];
+            raw[..11].copy_from_slice(name);
+            raw[11] = ATTRIBUTE_DIRECTORY;
+            raw[20..22].copy_from_slice(&((target >> 16) as u16).to_le_bytes());
+            raw[26..28].copy_from_slice(&(target as u16).to_le_bytes());
+        }
+        let sector = volume.cluster_sector(cluster);
+        volume.write_sector(sector, &data)?;
+        match volume.insert(parent, name, ATTRIBUTE_DIRECTORY, cluster, 0) {
+            Ok(inode) => Ok(inode),
+            Err(message) => {
+                volume.free_chain(cluster)?;
+                Err(message)
+            }
+        }
+    }
+
+    fn read(&self, inode: usize, offset: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        self.volume.lock().read(inode, offset, buffer)
+    }
+
+    fn write(&self, inode: usize, offset: usize, data: &[u8]) -> Result<usize, &'static str> {
+        self.volume.lock().write(inode, offset, data)
+    }
+
+    fn truncate(&self, inode: usize, size: usize) -> Result<(), &'static str> {
+        self.volume.lock().resize(inode, size)
+    }
+
+    fn stat(&self, inode: usize) -> Result<Stat, &'static str> {
+        let (_, size, attributes) = self.volume.lock().inode(inode)?;
+        Ok(Stat { directory: attributes & ATTRIBUTE_DIRECTORY != 0, size: size as usize })
+    }
+
+    fn readdir(&self, directory: usize, f: &mut dyn FnMut(&str, Stat)) -> Result<(), &'static str> {
+        let mut volume = self.volume.lock();
+        let directory = volume.directory(directory)?;
+        volume.scan(directory, &mut |entry| {
+            f(entry.name(), Stat { directory: entry.directory(), size: entry.size as usize });
+            false
+        })?;
+        Ok(())
+    }
+
+    fn unlink(&self, directory: usize, name: &str) -> Result<(), &'static str> {
+        let mut volume = self.volume.lock();
+        let directory = volume.directory(directory)?;
+        let entry = volume.find(directory, name)?.ok_or("No such file or directory")?;
+        if entry.directory() && volume.scan(Directory::Chain(entry.cluster), &mut |_| true)?.is_some() {
+            return Err("Directory not empty");
+        }
+        volume.erase(directory, &entry)?;
+        volume.free_chain(entry.cluster)
+    }
+
+    fn rename(&self, directory: usize, name: &str, target: usize, new_name: &str) -> Result<(), &'static str> {
+        let mut volume = self.volume.lock();
+        let source = volume.directory(directory)?;
+        let destination = volume.directory(target)?;
+        if !valid_name(new_name) {
+            return Err("Invalid name");
+        }
+        let entry = volume.find(source, name)?.ok_or("No such file or directory")?;
+        let destination_cluster = volume.parent_cluster(destination);
+        if entry.directory() && volume.within(destination_cluster, entry.cluster)? {
+            return Err("Cannot move a directory into itself");
+        }
+        if let Some(existing) = volume.find(destination, new_name)? {
+            // renaming to a different case of the same name is allowed
+            if existing.slot != entry.slot || volume.parent_cluster(source) != destination_cluster {
+                return Err("File exists");
+            }
+        }
+        volume.insert(destination, new_name, entry.attributes, entry.cluster, entry.size)?;
+        volume.erase(source, &entry)?;
+        if entry.directory() && volume.parent_cluster(source) != destination_cluster {
+            volume.set_parent(entry.cluster, destination_cluster)?;
+        }
+        Ok(())
+    }
+
+    fn usage(&self) -> (usize, usize) {
+        let mut volume = self.volume.lock();
+        let size = volume.clusters as usize * volume.cluster_bytes();
+        let free = volume.free_clusters().unwrap_or(volume.clusters) as usize * volume.cluster_bytes();
+        (size, size - free)
+    }
+
+    fn unmount(&self) {
+        let mut volume = self.volume.lock();
+        volume.mounted = false;
+        volume.cached = None;
+    }
+}
+
+/// Formats a device with FAT12 below 4 MiB, FAT16 below 512 MiB and FAT32 above, and returns
+/// the FAT type and the cluster size.
+pub fn format(device: &str) -> Result<(&'static str, usize), &'static str> {
+    let (disk, start, sectors) = ata::device(device)?;
+    if fs::device_mounted(disk, start, sectors) {
+        return Err("Device is mounted");
+    }
+    if sectors < 128 {
+        return Err("Device too small");
+    }
+    let kind = match sectors {
+        0..=8399 => Kind::Fat12,
+        8400..=1_048_575 => Kind::Fat16,
+        _ => Kind::Fat32,
+    };
+    let (reserved, root_entries, bits, limits) = match kind {
+        Kind::Fat12 => (1, 224, 12, 1..=4084),
+        Kind::Fat16 => (1, 512, 16, 4085..=65524),
+        Kind::Fat32 => (32, 0, 32, 65525..=0x0FFF_FFF5),
+    };
+    let root_sectors = (root_entries * ENTRY_SIZE as u64).div_ceil(SECTOR_SIZE as u64);
+    let fats = 2;
+    // the smallest cluster size, 4 KiB at least on FAT32, that keeps the count in range
+    let mut layout = None;
+    for shift in if kind == Kind::Fat32 { 3..8 } else { 0..8 } {
+        let sectors_per_cluster = 1u64 << shift;
+        let mut fat_sectors = 1;
+        let clusters = loop {
+            let clusters = (sectors - reserved - fats * fat_sectors - root_sectors) / sectors_per_cluster;
+            let needed = ((clusters + 2) * bits).div_ceil(8).div_ceil(SECTOR_SIZE as u64);
+            if needed <= fat_sectors {
+                break clusters;
+            }
+            fat_sectors = needed;
+        };
+        if limits.contains(&clusters) {
+            layout = Some((sectors_per_cluster, fat_sectors));
+            break;
+        }
+    }
+    let (sectors_per_cluster, fat_sectors) = layout.ok_or("No suitable cluster size")?;
+
+    let mut boot = [0u8; SECTOR_SIZE];
+    let boot_code = if kind == Kind::Fat32 { 0x5A } else { 0x3E };
+    boot[..3].copy_from_slice(&[0xEB, boot_code as u8 - 2, 0x90]);
+    boot[3..11].copy_from_slice(b"H-3X    ");
+    boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    boot[13] = sectors_per_cluster as u8;
+    boot[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+    boot[16] = fats as u8;
+    boot[17..19].copy_from_slice(&(root_entries as u16).to_le_bytes());
+    if sectors < 0x10000 && kind != Kind::Fat32 {
+        boot[19..21].copy_from_slice(&(sectors as u16).to_le_bytes());
+    } else {
+        boot[32..36].copy_from_slice(&(sectors.min(u32::MAX as u64) as u32).to_le_bytes());
+    }
+    boot[21] = MEDIA;
+    boot[24..26].copy_from_slice(&63u16.to_le_bytes());
+    boot[26..28].copy_from_slice(&255u16.to_le_bytes());
+    boot[28..32].copy_from_slice(&(start as u32).to_le_bytes());
+    let serial = unsafe { core::arch::x86_64::_rdtsc() } as u32;
+    let extended = if kind == Kind::Fat32 {
+        boot[36..40].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        // FSInfo sector and boot sector backup
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        boot[50..52].copy_from_slice(&6u16.to_le_bytes());
+        64
+    } else {
+        boot[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+        36
+    };
+    boot[extended] = 0x80;
+    boot[extended + 2] = 0x29;
+    boot[extended + 3..extended + 7].copy_from_slice(&serial.to_le_bytes());
+    boot[extended + 7..extended + 18].copy_from_slice(b"NO NAME    ");
+    boot[extended + 18..extended + 26].copy_from_slice(match kind {
+        Kind::Fat12 => b"FAT12   ",
+        Kind::Fat16 => b"FAT16   ",
+        Kind::Fat32 => b"FAT32   ",
+    });
+    // the boot code reports a non-bootable disk to the BIOS
+    boot[boot_code..boot_code + 2].copy_from_slice(&[0xCD, 0x18]);
+    boot[510..].copy_from_slice(&[0x55, 0xAA]);
+
+    // clear the reserved sectors, the FATs and the root directory
+    let root_clusters = if kind == Kind::Fat32 { sectors_per_cluster } else { root_sectors };
+    let zeros = [0u8; SECTOR_SIZE * 8];
+    let mut sector = 0;
+    let end = reserved + fats * fat_sectors + root_clusters;
+    while sector < end {
+        let count = (end - sector).min(8);
+        ata::write(disk, start + sector, &zeros[..count as usize * SECTOR_SIZE])?;
+        sector += count;
+    }
+    ata::write(disk, start, &boot)?;
+    if kind == Kind::Fat32 {
+        let mut info = [0u8; SECTOR_SIZE];
+        info[..4].copy_from_slice(b"RRaA");
+        info[484..488].copy_from_slice(b"rrAa");
+        let clusters = (sectors - reserved - fats * fat_sectors) / sectors_per_cluster;
+        info[488..492].copy_from_slice(&(clusters as u32 - 1).to_le_bytes());
+        info[492..496].copy_from_slice(&3u32.to_le_bytes());
+        info[510..].copy_from_slice(&[0x55, 0xAA]);
+        ata::write(disk, start + 1, &info)?;
+        ata::write(disk, start + 6, &boot)?;
+        ata::write(disk, start + 7, &info)?;
+    }
+    // the first two FAT entries hold the media byte and an end of chain; FAT32 also ends the
+    // root directory's chain at cluster 2
+    let mut first = [0u8; SECTOR_SIZE];
+    let entries: &[u8] = match kind {
+        Kind::Fat12 => &[MEDIA, 0xFF, 0xFF],
+        Kind::Fat16 => &[MEDIA, 0xFF, 0xFF, 0xFF],
+        Kind::Fat32 => &[MEDIA, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F],
+    };
+    first[..entries.len()].copy_from_slice(entries);
+    for copy in 0..fats {
+        ata::write(disk, start + reserved + copy * fat_sectors, &first)?;
+    }
+    Ok((kind.name(), sectors_per_cluster as usize * SECTOR_SIZE))
+}
@@ -0,0 +1,467 @@
+use spin::Mutex;
+use crate::console::{self, CONSOLES};
+use crate::{ata, ext2, fat, ramfs};
+
+/// Metadata of a file or directory.
+#[derive(Clone, Copy)]
+pub struct Stat {
+    pub directory: bool,
+    pub size: usize,
+}
+
+/// A concrete filesystem. Files and directories are named by inode numbers that only mean
+/// something to the filesystem that handed them out; the VFS resolves paths, mount points and
+/// file descriptors on top of these calls.
+pub trait FileSystem: Sync {
+    /// The filesystem type shown by `mount` and `df`.
+    fn kind(&self) -> &'static str;
+    fn root(&self) -> usize;
+    /// Finds an entry of a directory by name.
+    fn lookup(&self, directory: usize, name: &str) -> Result<usize, &'static str>;
+    /// Creates an empty file or directory.
+    fn create(&self, directory: usize, name: &str, is_directory: bool) -> Result<usize, &'static str>;
+    /// Reads from a file at `offset` and returns the number of bytes read, 0 at the end.
+    fn read(&self, inode: usize, offset: usize, buffer: &mut [u8]) -> Result<usize, &'static str>;
+    /// Writes to a file at `offset`, growing it as needed.
+    fn write(&self, inode: usize, offset: usize, data: &[u8]) -> Result<usize, &'static str>;
+    fn truncate(&self, inode: usize, size: usize) -> Result<(), &'static str>;
+    fn stat(&self, inode: usize) -> Result<Stat, &'static str>;
+    /// Calls `f` with the name and metadata of each entry of a directory.
+    fn readdir(&self, directory: usize, f: &mut dyn FnMut(&str, Stat)) -> Result<(), &'static str>;
+    /// Deletes a file or an empty directory.
+    fn unlink(&self, directory: usize, name: &str) -> Result<(), &'static str>;
+    /// Moves an entry to another directory of the same filesystem, possibly renaming it.
+    fn rename(&self, directory: usize, name: &str, target: usize, new_name: &str) -> Result<(), &'static str>;
+    /// Returns the size and the used space in bytes.
+    fn usage(&self) -> (usize, usize);
+    /// Called when the filesystem is unmounted, to write back or release it.
+    fn unmount(&self) {}
+}
+
+/// Creates a filesystem to mount from the name of a device, which may be ignored.
+type Mounter = fn(&str) -> Result<&'static dyn FileSystem, &'static str>;
+
+/// The filesystem types known to `mount`.
+const TYPES: [(&str, Mounter); 3] = [("ext2", ext2::instance), ("fat", fat::instance), ("ramfs", ramfs::instance)];
+
+pub const PATH_LENGTH: usize = 128;
+const DEVICE_LENGTH: usize = 16;
+pub const MOUNTS: usize = 8;
+pub const FILES: usize = 16;
+
+/// Flags for `open`.
+pub const READ: u8 = 1;
+pub const WRITE: u8 = 2;
+/// Creates the file if it does not exist.
+pub const CREATE: u8 = 4;
+pub const TRUNCATE: u8 = 8;
+/// Moves to the end of the file before each write.
+pub const APPEND: u8 = 16;
+
+pub enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
+
+/// An absolute path without `.` and `..` components or repeated slashes.
+#[derive(Clone, Copy)]
+struct Path {
+    bytes: [u8; PATH_LENGTH],
+    length: usize,
+}
+
+const ROOT_PATH: Path = Path { bytes: [b'/'; PATH_LENGTH], length: 1 };
+
+impl Path {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap_or("/")
+    }
+
+    fn push(&mut self, name: &str) -> Result<(), &'static str> {
+        let slash = if self.length > 1 { 1 } else { 0 };
+        if self.length + slash + name.len() > PATH_LENGTH {
+            return Err("Path too long");
+        }
+        if slash == 1 {
+            self.bytes[self.length] = b'/';
+        }
+        self.bytes[self.length + slash..self.length + slash + name.len()].copy_from_slice(name.as_bytes());
+        self.length += slash + name.len();
+        Ok(())
+    }
+
+    /// Removes the last name and returns it, or `None` at the root.
+    fn pop(&mut self) -> Option<&str> {
+        if self.length == 1 {
+            return None;
+        }
+        let start = self.as_str().rfind('/').unwrap_or(0);
+        let old = self.length;
+        self.length = start.max(1);
+        core::str::from_utf8(&self.bytes[start + 1..old]).ok()
+    }
+
+    /// Returns `true` if this path is `prefix` or inside it.
+    fn within(&self, prefix: &str) -> bool {
+        let path = self.as_str();
+        prefix == "/" || path == prefix || (path.starts_with(prefix) && path.as_bytes()[prefix.len()] == b'/')
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Mount {
+    path: Path,
+    device: [u8; DEVICE_LENGTH],
+    device_length: usize,
+    fs: &'static dyn FileSystem,
+}
+
+#[derive(Clone, Copy)]
+struct OpenFile {
+    mount: usize,
+    fs: &'static dyn FileSystem,
+    inode: usize,
+    offset: usize,
+    flags: u8,
+}
+
+static MOUNT_TABLE: Mutex<[Option<Mount>; MOUNTS]> = Mutex::new([None; MOUNTS]);
+static OPEN_FILES: Mutex<[Option<OpenFile>; FILES]> = Mutex::new([None; FILES]);
+/// The working directory of each console.
+static CWD: Mutex<[Path; CONSOLES]> = Mutex::new([ROOT_PATH; CONSOLES]);
+
+/// Mounts the RAM filesystem on `/`.
+pub fn init() {
+    ramfs::ROOT_FS.format();
+    MOUNT_TABLE.lock()[0] = Some(Mount {
+        path: ROOT_PATH,
+        device: [0; DEVICE_LENGTH],
+        device_length: 0,
+        fs: &ramfs::ROOT_FS,
+    });
+}
+
+/// Turns a path, absolute or relative to the working directory, into an absolute one.
+fn absolute(path: &str) -> Result<Path, &'static str> {
+    let mut result = if path.starts_with('/') { ROOT_PATH } else { CWD.lock()[console::current()] };
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                result.pop();
+            }
+            name => result.push(name)?,
+        }
+    }
+    Ok(result)
+}
+
+/// Finds the mount holding a path and walks the rest of the path from its root.
+fn locate(path: &Path) -> Result<(usize, &'static dyn FileSystem, usize), &'static str> {
+    let (index, mount) = {
+        let mounts = MOUNT_TABLE.lock();
+        mounts
+            .iter()
+            .enumerate()
+            .filter_map(|(index, mount)| mount.map(|mount| (index, mount)))
+            .filter(|(_, mount)| path.within(mount.path.as_str()))
+            .max_by_key(|(_, mount)| mount.path.length)
+            .ok_or("Nothing is mounted")?
+    };
+    let mut inode = mount.fs.root();
+    let rest = &path.as_str()[mount.path.length.min(path.length)..];
+    for name in rest.split('/').filter(|name| !name.is_empty()) {
+        inode = mount.fs.lookup(inode, name)?;
+    }
+    Ok((index, mount.fs, inode))
+}
+
+fn is_mount_point(path: &Path) -> bool {
+    MOUNT_TABLE.lock().iter().flatten().any(|mount| mount.path.as_str() == path.as_str())
+}
+
+/// Resolves the directory holding a path and returns it with the last name of the path.
+fn parent(path: &str) -> Result<(usize, &'static dyn FileSystem, usize, Path), &'static str> {
+    let full = absolute(path)?;
+    let mut directory = full;
+    if directory.pop().is_none() {
+        return Err("Invalid name");
+    }
+    let (mount, fs, inode) = locate(&directory)?;
+    Ok((mount, fs, inode, full))
+}
+
+/// Returns the last name of a path that is not the root.
+fn name_of(path: &Path) -> &str {
+    &path.as_str()[path.as_str().rfind('/').unwrap_or(0) + 1..]
+}
+
+pub fn stat(path: &str) -> Result<Stat, &'static str> {
+    let (_, fs, inode) = locate(&absolute(path)?)?;
+    fs.stat(inode)
+}
+
+/// Calls `f` with the name and metadata of each entry of a directory.
+pub fn readdir<F: FnMut(&str, Stat)>(path: &str, mut f: F) -> Result<(), &'static str> {
+    let (_, fs, inode) = locate(&absolute(path)?)?;
+    fs.readdir(inode, &mut f)
+}
+
+pub fn mkdir(path: &str) -> Result<(), &'static str> {
+    let (_, fs, directory, full) = parent(path)?;
+    fs.create(directory, name_of(&full), true).map(|_| ())
+}
+
+/// Deletes a file, or an empty directory if `directory` is set.
+fn remove(path: &str, directory: bool) -> Result<(), &'static str> {
+    let (mount, fs, parent, full) = parent(path)?;
+    let name = name_of(&full);
+    let inode = fs.lookup(parent, name)?;
+    if fs.stat(inode)?.directory != directory {
+        return Err(if directory { "Not a directory" } else { "Is a directory" });
+    }
+    if is_mount_point(&full) {
+        return Err("Is a mount point");
+    }
+    // an open descriptor would go on using the freed inode or clusters
+    let files = OPEN_FILES.lock();
+    if files.iter().flatten().any(|file| file.mount == mount && file.inode == inode) {
+        return Err("Resource busy");
+    }
+    fs.unlink(parent, name)
+}
+
+pub fn unlink(path: &str) -> Result<(), &'static str> {
+    remove(path, false)
+}
+
+pub fn rmdir(path: &str) -> Result<(), &'static str> {
+    remove(path, true)
+}
+
+/// Where `source` goes when moved or copied to `destination`: into it if it is a directory,
+/// otherwise under the name it gives.
+fn destination(source: &Path, destination: &str) -> Result<Path, &'static str> {
+    let mut target = absolute(destination)?;
+    if let Ok((_, fs, inode)) = locate(&target) {
+        if !fs.stat(inode)?.directory {
+            return Err("File exists");
+        }
+        target.push(name_of(source))?;
+    }
+    Ok(target)
+}
+
+/// Moves or renames a file or directory within a filesystem.
+pub fn rename(source: &str, target: &str) -> Result<(), &'static str> {
+    let (mount, fs, directory, from) = parent(source)?;
+    let to = destination(&from, target)?;
+    // the mount table would keep the old paths
+    if MOUNT_TABLE.lock().iter().flatten().any(|mount| mount.path.within(from.as_str())) {
+        return Err("Is or contains a mount point");
+    }
+    let (target_mount, _, target_directory, _) = parent(to.as_str())?;
+    if target_mount != mount {
+        return Err("Cannot move between filesystems");
+    }
+    fs.rename(directory, name_of(&from), target_directory, name_of(&to))
+}
+
+/// Copies a file, possibly to another filesystem.
+pub fn copy(source: &str, target: &str) -> Result<(), &'static str> {
+    let from = absolute(source)?;
+    if stat(from.as_str())?.directory {
+        return Err("Is a directory");
+    }
+    let to = destination(&from, target)?;
+    let input = open(from.as_str(), READ)?;
+    let result = open(to.as_str(), WRITE | CREATE | TRUNCATE).and_then(|output| {
+        let mut buffer = [0; 512];
+        let result = loop {
+            match read(input, &mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(count) => {
+                    if let Err(message) = write(output, &buffer[..count]) {
+                        break Err(message);
+                    }
+                }
+                Err(message) => break Err(message),
+            }
+        };
+        close(output);
+        result
+    });
+    close(input);
+    result
+}
+
+/// Changes the working directory of the running console.
+pub fn chdir(path: &str) -> Result<(), &'static str> {
+    let path = absolute(path)?;
+    let (_, fs, inode) = locate(&path)?;
+    if !fs.stat(inode)?.directory {
+        return Err("Not a directory");
+    }
+    CWD.lock()[console::current()] = path;
+    Ok(())
+}
+
+/// Calls `f` with the working directory of the running console.
+pub fn cwd<F: FnOnce(&str)>(f: F) {
+    let path = CWD.lock()[console::current()];
+    f(path.as_str());
+}
+
+/// Opens a file and returns its file descriptor.
+pub fn open(path: &str, flags: u8) -> Result<usize, &'static str> {
+    let full = absolute(path)?;
+    let (mount, fs, inode) = match locate(&full) {
+        Ok(found) => found,
+        Err(_) if flags & CREATE != 0 => {
+            let (mount, fs, directory, full) = parent(path)?;
+            (mount, fs, fs.create(directory, name_of(&full), false)?)
+        }
+        Err(message) => return Err(message),
+    };
+    if flags & (WRITE | APPEND) != 0 && fs.stat(inode)?.directory {
+        return Err("Is a directory");
+    }
+    let mut files = OPEN_FILES.lock();
+    let fd = files.iter().position(|file| file.is_none()).ok_or("Too many open files")?;
+    if flags & TRUNCATE != 0 {
+        fs.truncate(inode, 0)?;
+    }
+    files[fd] = Some(OpenFile { mount, fs, inode, offset: 0, flags });
+    Ok(fd)
+}
+
+pub fn close(fd: usize) {
+    if let Some(file) = OPEN_FILES.lock().get_mut(fd) {
+        *file = None;
+    }
+}
+
+fn file(fd: usize) -> Result<OpenFile, &'static str> {
+    OPEN_FILES.lock().get(fd).copied().flatten().ok_or("Bad file descriptor")
+}
+
+fn set_offset(fd: usize, offset: usize) {
+    if let Some(Some(file)) = OPEN_FILES.lock().get_mut(fd) {
+        file.offset = offset;
+    }
+}
+
+/// Reads from the file offset onwards and returns the number of bytes read, 0 at the end.
+pub fn read(fd: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let file = file(fd)?;
+    if file.flags & READ == 0 {
+        return Err("File not open for reading");
+    }
+    let count = file.fs.read(file.inode, file.offset, buffer)?;
+    set_offset(fd, file.offset + count);
+    Ok(count)
+}
+
+pub fn write(fd: usize, data: &[u8]) -> Result<usize, &'static str> {
+    let file = file(fd)?;
+    if file.flags & (WRITE | APPEND) == 0 {
+        return Err("File not open for writing");
+    }
+    let offset = if file.flags & APPEND != 0 { file.fs.stat(file.inode)?.size } else { file.offset };
+    let count = file.fs.write(file.inode, offset, data)?;
+    set_offset(fd, offset + count);
+    Ok(count)
+}
+
+/// Moves the file offset and returns the new one.
+pub fn seek(fd: usize, position: SeekFrom) -> Result<usize, &'static str> {
+    let file = file(fd)?;
+    let offset = match position {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(delta) => file.offset.checked_add_signed(delta),
+        SeekFrom::End(delta) => file.fs.stat(file.inode)?.size.checked_add_signed(delta),
+    }
+    .ok_or("Invalid offset")?;
+    set_offset(fd, offset);
+    Ok(offset)
+}
+
+/// Sets the size of an open file.
+pub fn truncate(fd: usize, size: usize) -> Result<(), &'static str> {
+    let file = file(fd)?;
+    if file.flags & (WRITE | APPEND) == 0 {
+        return Err("File not open for writing");
+    }
+    file.fs.truncate(file.inode, size)
+}
+
+/// Mounts a filesystem of type `kind` on an existing directory.
+pub fn mount(kind: &str, path: &str, device: &str) -> Result<(), &'static str> {
+    let mounter = TYPES
+        .iter()
+        .find(|(name, _)| *name == kind)
+        .map(|&(_, mounter)| mounter)
+        .ok_or("Unknown filesystem type")?;
+    if device.len() > DEVICE_LENGTH {
+        return Err("Device name too long");
+    }
+    let path = absolute(path)?;
+    let (_, fs, inode) = locate(&path)?;
+    if !fs.stat(inode)?.directory {
+        return Err("Not a directory");
+    }
+    if is_mount_point(&path) {
+        return Err("Already mounted");
+    }
+    let slot = MOUNT_TABLE.lock().iter().position(|mount| mount.is_none()).ok_or("Mount table full")?;
+    let fs = mounter(device)?;
+    let mut mount = Mount { path, device: [0; DEVICE_LENGTH], device_length: device.len(), fs };
+    mount.device[..device.len()].copy_from_slice(device.as_bytes());
+    MOUNT_TABLE.lock()[slot] = Some(mount);
+    Ok(())
+}
+
+pub fn umount(path: &str) -> Result<(), &'static str> {
+    let path = absolute(path)?;
+    let mut mounts = MOUNT_TABLE.lock();
+    let index = mounts
+        .iter()
+        .position(|mount| matches!(mount, Some(mount) if mount.path.as_str() == path.as_str()))
+        .ok_or("Not mounted")?;
+    if path.length == 1 {
+        return Err("Cannot unmount the root filesystem");
+    }
+    let nested = mounts.iter().enumerate().any(|(other, mount)| {
+        other != index && matches!(mount, Some(mount) if mount.path.within(path.as_str()))
+    });
+    let open = OPEN_FILES.lock().iter().flatten().any(|file| file.mount == index);
+    if nested || open {
+        return Err("Filesystem is busy");
+    }
+    if let Some(mount) = mounts[index].take() {
+        mount.fs.unmount();
+    }
+    Ok(())
+}
+
+/// Returns `true` if a mounted filesystem uses any of the `sectors` sectors of `disk` from
+/// `start`, whatever its type.
+pub fn device_mounted(disk: usize, start: u64, sectors: u64) -> bool {
+    let mounts = *MOUNT_TABLE.lock();
+    mounts.iter().flatten().any(|mount| {
+        let device = core::str::from_utf8(&mount.device[..mount.device_length]).unwrap_or("");
+        matches!(ata::device(device), Ok((other, first, count))
+            if other == disk && start < first + count && first < start + sectors)
+    })
+}
+
+/// Calls `f` with the path, device, type and usage of each mounted filesystem.
+pub fn mounts<F: FnMut(&str, &str, &str, (usize, usize))>(mut f: F) {
+    let mounts = *MOUNT_TABLE.lock();
+    for mount in mounts.iter().flatten() {
+        let device = core::str::from_utf8(&mount.device[..mount.device_length]).unwrap_or("");
+        let device = if device.is_empty() { mount.fs.kind() } else { device };
+        f(mount.path.as_str(), device, mount.fs.kind(), mount.fs.usage());
+    }
+}
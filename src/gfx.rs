@@ -0,0 +1,337 @@
+use core::fmt::{self, Write};
+use spin::Mutex;
+use crate::cp437;
+use crate::status;
+use crate::vga::{self, GLYPH_SIZE, MODE_13H};
+use crate::vga_buffer::{self, Color, Screen, WRITER};
+
+/// The size of the mode 13h screen in pixels.
+pub const WIDTH: usize = 320;
+pub const HEIGHT: usize = 200;
+
+/// Mode 13h maps one byte per pixel, row by row, at this address.
+const FRAMEBUFFER: *mut u8 = 0xA0000 as *mut u8;
+
+/// Width of a glyph drawn from the text mode font.
+const GLYPH_WIDTH: usize = 8;
+const TAB_WIDTH: usize = 8;
+
+/// The text screen, put back on `leave`.
+static SCREEN: Mutex<Option<Screen>> = Mutex::new(None);
+
+/// Where `print!` output goes while graphics are shown.
+struct Terminal {
+    row: usize,
+    col: usize,
+    foreground: u8,
+    background: u8,
+}
+
+static TERMINAL: Mutex<Terminal> = Mutex::new(Terminal {
+    row: 0,
+    col: 0,
+    foreground: 15,
+    background: 0,
+});
+
+static mut ACTIVE: bool = false;
+
+/// Returns `true` while mode 13h is set.
+pub fn active() -> bool {
+    unsafe { ACTIVE }
+}
+
+/// Returns the palette index of a color in the 6x6x6 color cube of the default palette, with
+/// each component from 0 to 5.
+pub fn rgb(red: u8, green: u8, blue: u8) -> u8 {
+    32 + red.min(5) * 36 + green.min(5) * 6 + blue.min(5)
+}
+
+/// Scales `step` out of `steps` to a DAC intensity (0-63).
+fn level(step: u8, steps: u8) -> u8 {
+    (step as u16 * 63 / steps as u16) as u8
+}
+
+/// Switches to 320x200 with 256 colors.
+///
+/// The text screen, registers, DAC and the font in plane 2 are saved first, since mode 13h
+/// uses the same video memory. Indices 0-15 of the default palette keep the text colors,
+/// 16-31 are grays and 32-247 a color cube (see `rgb`).
+pub fn enter() {
+    if active() {
+        return;
+    }
+    let screen = WRITER.lock().save();
+    let mut text_colors = [(0, 0, 0); 16];
+    for (index, color) in text_colors.iter_mut().enumerate() {
+        *color = vga_buffer::palette(Color::from_index(index as u8));
+    }
+    *SCREEN.lock() = Some(screen);
+    vga::save_text_mode();
+
+    MODE_13H.write();
+    for index in 0..=255u8 {
+        let rgb = match index {
+            0..=15 => text_colors[index as usize],
+            16..=31 => {
+                let gray = level(index - 16, 15);
+                (gray, gray, gray)
+            }
+            32..=247 => {
+                let cube = index - 32;
+                (level(cube / 36, 5), level(cube / 6 % 6, 5), level(cube % 6, 5))
+            }
+            _ => (0, 0, 0),
+        };
+        vga::write_dac(index, rgb);
+    }
+    unsafe {
+        ACTIVE = true;
+    }
+    clear(0);
+    let mut terminal = TERMINAL.lock();
+    terminal.row = 0;
+    terminal.col = 0;
+}
+
+/// Returns to the text mode that was set before `enter`, with its font, palette and screen.
+pub fn leave() {
+    if !active() {
+        return;
+    }
+    vga::restore_text_mode();
+    unsafe {
+        ACTIVE = false;
+    }
+    if let Some(screen) = SCREEN.lock().take() {
+        WRITER.lock().restore(&screen);
+    }
+    status::refresh();
+}
+
+/// Sets one pixel; coordinates outside the screen are ignored.
+pub fn set_pixel(x: i32, y: i32, color: u8) {
+    if !active() || x < 0 || y < 0 || x >= WIDTH as i32 || y >= HEIGHT as i32 {
+        return;
+    }
+    unsafe {
+        FRAMEBUFFER.add(y as usize * WIDTH + x as usize).write_volatile(color);
+    }
+}
+
+/// Returns the color of a pixel, or 0 outside the screen.
+pub fn pixel(x: i32, y: i32) -> u8 {
+    if !active() || x < 0 || y < 0 || x >= WIDTH as i32 || y >= HEIGHT as i32 {
+        return 0;
+    }
+    unsafe { FRAMEBUFFER.add(y as usize * WIDTH + x as usize).read_volatile() }
+}
+
+/// Fills the whole screen with one color.
+pub fn clear(color: u8) {
+    fill_rect(0, 0, WIDTH as i32, HEIGHT as i32, color);
+}
+
+/// Draws a line between two points (inclusive) with Bresenham's algorithm.
+pub fn line(x0: i32, y0: i32, x1: i32, y1: i32, color: u8) {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+    let (mut x, mut y) = (x0, y0);
+    let mut error = dx + dy;
+    loop {
+        set_pixel(x, y, color);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Draws the outline of a rectangle.
+pub fn rect(x: i32, y: i32, width: i32, height: i32, color: u8) {
+    if width <= 0 || height <= 0 {
+        return;
+    }
+    let (right, bottom) = (x + width - 1, y + height - 1);
+    line(x, y, right, y, color);
+    line(x, bottom, right, bottom, color);
+    line(x, y, x, bottom, color);
+    line(right, y, right, bottom, color);
+}
+
+/// Fills a rectangle, clipped to the screen.
+pub fn fill_rect(x: i32, y: i32, width: i32, height: i32, color: u8) {
+    if !active() {
+        return;
+    }
+    let (left, top) = (x.max(0), y.max(0));
+    let right = (x + width).min(WIDTH as i32);
+    let bottom = (y + height).min(HEIGHT as i32);
+    for row in top..bottom {
+        for col in left..right {
+            unsafe {
+                FRAMEBUFFER.add(row as usize * WIDTH + col as usize).write_volatile(color);
+            }
+        }
+    }
+}
+
+/// Draws the outline of a circle with the midpoint algorithm.
+pub fn circle(cx: i32, cy: i32, radius: i32, color: u8) {
+    let (mut x, mut y) = (radius, 0);
+    let mut error = 1 - radius;
+    while x >= y {
+        for &(px, py) in &[(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+            set_pixel(cx + px, cy + py, color);
+        }
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+    }
+}
+
+/// Fills a circle with horizontal spans.
+pub fn fill_circle(cx: i32, cy: i32, radius: i32, color: u8) {
+    for dy in -radius..=radius {
+        let mut dx = 0;
+        while (dx + 1) * (dx + 1) + dy * dy <= radius * radius {
+            dx += 1;
+        }
+        fill_rect(cx - dx, cy + dy, 2 * dx + 1, 1, color);
+    }
+}
+
+/// Copies a block of pixels, `width` per row, to the screen with its top left corner at
+/// `(x, y)`. Pixels of the `transparent` color are skipped.
+pub fn blit(x: i32, y: i32, width: usize, pixels: &[u8], transparent: Option<u8>) {
+    if width == 0 {
+        return;
+    }
+    for (index, &color) in pixels.iter().enumerate() {
+        if Some(color) != transparent {
+            set_pixel(x + (index % width) as i32, y + (index / width) as i32, color);
+        }
+    }
+}
+
+/// Sets the red, green and blue intensities (0-63 each) of a palette index.
+pub fn set_palette(index: u8, rgb: (u8, u8, u8)) {
+    vga::write_dac(index, rgb);
+}
+
+/// Returns the red, green and blue intensities (0-63 each) of a palette index.
+pub fn palette(index: u8) -> (u8, u8, u8) {
+    vga::read_dac(index)
+}
+
+/// Returns the height of the glyphs drawn by `draw_char`, that of the text mode font.
+pub fn font_height() -> usize {
+    vga::with_saved_font(|_, height| height)
+}
+
+/// Draws a character from the text mode font with its top left corner at `(x, y)`, leaving
+/// the background alone if `background` is `None`.
+pub fn draw_char(x: i32, y: i32, character: char, foreground: u8, background: Option<u8>) {
+    let glyph = cp437::encode(character) as usize * GLYPH_SIZE;
+    vga::with_saved_font(|font, height| {
+        for row in 0..height {
+            let bits = font[glyph + row];
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x80 >> col) != 0 {
+                    set_pixel(x + col as i32, y + row as i32, foreground);
+                } else if let Some(background) = background {
+                    set_pixel(x + col as i32, y + row as i32, background);
+                }
+            }
+        }
+    });
+}
+
+/// Draws a string on one line starting at `(x, y)`.
+pub fn draw_text(x: i32, y: i32, text: &str, foreground: u8, background: Option<u8>) {
+    for (index, character) in text.chars().enumerate() {
+        draw_char(x + (index * GLYPH_WIDTH) as i32, y, character, foreground, background);
+    }
+}
+
+impl Terminal {
+    fn rows(&self) -> usize {
+        HEIGHT / font_height()
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows() {
+            self.row += 1;
+            return;
+        }
+        // scroll the text area up by one line of text
+        let height = font_height();
+        let text_height = self.rows() * height;
+        for y in 0..text_height - height {
+            for x in 0..WIDTH {
+                unsafe {
+                    let pixel = FRAMEBUFFER.add((y + height) * WIDTH + x).read_volatile();
+                    FRAMEBUFFER.add(y * WIDTH + x).write_volatile(pixel);
+                }
+            }
+        }
+        fill_rect(0, (text_height - height) as i32, WIDTH as i32, height as i32, self.background);
+    }
+
+    fn write_character(&mut self, character: char) {
+        let columns = WIDTH / GLYPH_WIDTH;
+        match character {
+            '\n' => self.new_line(),
+            '\r' => self.col = 0,
+            '\t' => self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(columns - 1),
+            '\x08' => {
+                if self.col > 0 {
+                    self.col = self.col.min(columns) - 1;
+                    self.put(' ');
+                }
+            }
+            character => {
+                if self.col >= columns {
+                    self.new_line();
+                }
+                self.put(character);
+                self.col += 1;
+            }
+        }
+    }
+
+    fn put(&self, character: char) {
+        let (x, y) = ((self.col * GLYPH_WIDTH) as i32, (self.row * font_height()) as i32);
+        draw_char(x, y, character, self.foreground, Some(self.background));
+    }
+}
+
+impl Write for Terminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for character in s.chars() {
+            self.write_character(character);
+        }
+        Ok(())
+    }
+}
+
+/// Prints to the graphics screen with the text mode font; `print!` ends up here while
+/// graphics are active.
+pub fn print(args: fmt::Arguments) {
+    TERMINAL.lock().write_fmt(args).unwrap();
+}
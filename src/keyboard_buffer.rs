@@ -1,82 +1,292 @@
 use x86_64::instructions::port::Port;
+use crate::keymap;
+use crate::commands;
+use crate::console::{self, CONSOLES};
+use crate::mouse;
+use crate::ps2;
+use crate::status;
+use crate::sysrq;
+use crate::vga_buffer::{self, WRITER};
+
+/// Characters of the numeric keypad with Num Lock on, for scancodes 0x47 to 0x53.
+const KEYPAD: &[u8; 13] = b"789-456+1230.";
 
 static mut SHIFT_PRESSED: bool = false;
+static mut CTRL_PRESSED: bool = false;
+static mut ALT_PRESSED: bool = false;
+static mut ALTGR_PRESSED: bool = false;
+static mut EXTENDED: bool = false;
+/// Set while Alt+SysRq is held down.
+static mut SYSRQ_ARMED: bool = false;
+/// The last key pressed with SysRq held, so that its autorepeat acts only once.
+static mut SYSRQ_KEY: u8 = 0;
+/// A SysRq action waiting for a point where it can take locks.
+static mut SYSRQ_ACTION: u8 = 0;
+static mut CAPS_LOCK: bool = false;
+static mut NUM_LOCK: bool = false;
+/// Bytes left of the six-byte Pause sequence, which starts with 0xE1 and has no release.
+static mut PAUSE_BYTES: u8 = 0;
+static mut DEAD_KEY: char = '\0';
+/// A character produced while nothing was reading input, per console.
+static mut PENDING: [char; CONSOLES] = ['\0'; CONSOLES];
 
+/// Waits for the next key press or mouse byte and returns the resulting character, or `'\0'`
+/// if the byte did not produce one.
+///
+/// Only the visible console receives input; tasks on the other consoles wait here, and every
+/// task lets the others run while it waits.
 pub fn read_char() -> char {
     let mut status_port: Port<u8> = Port::new(0x64);
     let mut data_port: Port<u8> = Port::new(0x60);
 
-    while unsafe { status_port.read() } & 1 == 0 {}
+    loop {
+        if console::current() == console::visible() {
+            let console = console::current();
+            unsafe {
+                if PENDING[console] != '\0' {
+                    let character = PENDING[console];
+                    PENDING[console] = '\0';
+                    return character;
+                }
+            }
+            let pasted = mouse::take_paste();
+            if pasted != '\0' {
+                return pasted;
+            }
+
+            let status = unsafe { status_port.read() };
+            if status & 1 != 0 {
+                let scancode = unsafe { data_port.read() };
+                let character = process(status, scancode);
+                run_sysrq();
+                if character != '\0' {
+                    WRITER.lock().snap_back();
+                }
+                return character;
+            }
+        }
+        vga_buffer::flush();
+        status::tick();
+        console::yield_now();
+    }
+}
+
+/// Handles any byte waiting in the controller without blocking, then lets the other consoles
+/// run.
+///
+/// Long-running commands call this so that SysRq, Ctrl+Alt+Del, console switching and the
+/// mouse keep working and their output shows up while they are not reading input; a resulting
+/// character is kept for the next `read_char`.
+pub fn poll() {
+    poll_keys();
+    run_sysrq();
+    vga_buffer::flush();
+    status::tick();
+    console::yield_now();
+}
 
+/// Handles any byte waiting in the controller without blocking or letting other tasks run.
+///
+/// Busy loops that may hold locks, such as clock waits, call this so that SysRq reboot
+/// and Ctrl+Alt+Del still work; other SysRq actions run at the next `poll` or `read_char`.
+pub fn poll_keys() {
+    if console::current() == console::visible() {
+        poll_controller();
+    }
+}
+
+/// Runs a SysRq action recorded by `process`.
+fn run_sysrq() {
+    let action = unsafe {
+        let action = SYSRQ_ACTION;
+        SYSRQ_ACTION = 0;
+        action
+    };
+    if action != 0 {
+        sysrq::handle(action);
+    }
+}
+
+fn poll_controller() {
+    let mut status_port: Port<u8> = Port::new(0x64);
+    let mut data_port: Port<u8> = Port::new(0x60);
+
+    let status = unsafe { status_port.read() };
+    if status & 1 == 0 {
+        return;
+    }
     let scancode = unsafe { data_port.read() };
+    let character = process(status, scancode);
+    if character != '\0' {
+        WRITER.lock().snap_back();
+    }
+    let console = console::current();
+    unsafe {
+        if character != '\0' && PENDING[console] == '\0' {
+            PENDING[console] = character;
+        }
+    }
+}
+
+fn process(status: u8, scancode: u8) -> char {
+    if status & 0x20 != 0 {
+        mouse::handle_byte(scancode);
+        return '\0';
+    }
+
+    unsafe {
+        if PAUSE_BYTES > 0 {
+            PAUSE_BYTES -= 1;
+            return '\0';
+        }
+        if scancode == 0xE1 {
+            PAUSE_BYTES = 5;
+            return '\0';
+        }
+    }
+
+    if scancode == 0xE0 {
+        unsafe { EXTENDED = true; }
+        return '\0';
+    }
+    let extended = unsafe { EXTENDED };
+    unsafe { EXTENDED = false; }
 
     if scancode & 0x80 != 0 {
         let key_released = scancode & 0x7F;
-        if key_released == 0x2A || key_released == 0x36 {
-            unsafe { SHIFT_PRESSED = false; }
+        unsafe {
+            match (extended, key_released) {
+                (false, 0x54) | (true, 0x37) => {
+                    SYSRQ_ARMED = false;
+                    SYSRQ_KEY = 0;
+                }
+                (_, 0x1D) => CTRL_PRESSED = false,
+                (false, 0x38) => ALT_PRESSED = false,
+                (true, 0x38) => ALTGR_PRESSED = false,
+                (false, 0x2A) | (false, 0x36) => SHIFT_PRESSED = false,
+                (_, key) if key == SYSRQ_KEY => SYSRQ_KEY = 0,
+                _ => (),
+            }
         }
         return '\0';
     }
 
-    if scancode == 0x2A || scancode == 0x36 {
-        unsafe { SHIFT_PRESSED = true; }
+    let alt = unsafe { ALT_PRESSED || ALTGR_PRESSED };
+    // Alt+Print Screen sends 0x54; holding it repeats the make code, which changes nothing
+    if (!extended && scancode == 0x54) || (extended && scancode == 0x37 && alt) {
+        unsafe { SYSRQ_ARMED = true; }
         return '\0';
     }
+    unsafe {
+        if SYSRQ_ARMED && !matches!(scancode, 0x1D | 0x38 | 0x2A | 0x36) {
+            if scancode != SYSRQ_KEY {
+                SYSRQ_KEY = scancode;
+                // the task may be stuck in a loop that never reaches `poll`
+                if scancode == sysrq::REBOOT {
+                    sysrq::handle(scancode);
+                } else {
+                    SYSRQ_ACTION = scancode;
+                }
+            }
+            return '\0';
+        }
+        if CTRL_PRESSED && alt && scancode == 0x53 {
+            commands::restart();
+        }
+    }
 
-    scancode_to_char(scancode)
+    if extended {
+        match scancode {
+            0x1D => unsafe { CTRL_PRESSED = true; },
+            0x38 => unsafe { ALTGR_PRESSED = true; },
+            0x49 if unsafe { SHIFT_PRESSED } => WRITER.lock().scroll_view(vga_buffer::height() as isize - 1),
+            0x51 if unsafe { SHIFT_PRESSED } => WRITER.lock().scroll_view(1 - vga_buffer::height() as isize),
+            0x1C => return '\n',
+            0x35 => return '/',
+            _ => (),
+        }
+        return '\0';
+    }
+
+    match scancode {
+        0x1D => unsafe { CTRL_PRESSED = true; },
+        0x38 => unsafe { ALT_PRESSED = true; },
+        0x2A | 0x36 => unsafe { SHIFT_PRESSED = true; },
+        0x3A => unsafe { CAPS_LOCK = !CAPS_LOCK; update_leds(); },
+        0x45 => unsafe { NUM_LOCK = !NUM_LOCK; update_leds(); },
+        0x3B..=0x40 if alt => console::show((scancode - 0x3B) as usize),
+        0x37 => return '*',
+        0x4A => return '-',
+        0x4E => return '+',
+        0x47..=0x53 if unsafe { NUM_LOCK } => return KEYPAD[(scancode - 0x47) as usize] as char,
+        _ => return apply_dead_key(scancode_to_char(scancode)),
+    }
+    '\0'
 }
 
 fn scancode_to_char(scancode: u8) -> char {
     match scancode {
-        0x02 => if unsafe { SHIFT_PRESSED } { '!' } else { '1' },
-        0x03 => if unsafe { SHIFT_PRESSED } { '@' } else { '2' },
-        0x04 => if unsafe { SHIFT_PRESSED } { '#' } else { '3' },
-        0x05 => if unsafe { SHIFT_PRESSED } { '$' } else { '4' },
-        0x06 => if unsafe { SHIFT_PRESSED } { '%' } else { '5' },
-        0x07 => if unsafe { SHIFT_PRESSED } { '^' } else { '6' },
-        0x08 => if unsafe { SHIFT_PRESSED } { '&' } else { '7' },
-        0x09 => if unsafe { SHIFT_PRESSED } { '*' } else { '8' },
-        0x0A => if unsafe { SHIFT_PRESSED } { '(' } else { '9' },
-        0x0B => if unsafe { SHIFT_PRESSED } { ')' } else { '0' },
-        0x10 => if unsafe { SHIFT_PRESSED } { 'Q' } else { 'q' },
-        0x11 => if unsafe { SHIFT_PRESSED } { 'W' } else { 'w' },
-        0x12 => if unsafe { SHIFT_PRESSED } { 'E' } else { 'e' },
-        0x13 => if unsafe { SHIFT_PRESSED } { 'R' } else { 'r' },
-        0x14 => if unsafe { SHIFT_PRESSED } { 'T' } else { 't' },
-        0x15 => if unsafe { SHIFT_PRESSED } { 'Y' } else { 'y' },
-        0x16 => if unsafe { SHIFT_PRESSED } { 'U' } else { 'u' },
-        0x17 => if unsafe { SHIFT_PRESSED } { 'I' } else { 'i' },
-        0x18 => if unsafe { SHIFT_PRESSED } { 'O' } else { 'o' },
-        0x19 => if unsafe { SHIFT_PRESSED } { 'P' } else { 'p' },
-        0x1E => if unsafe { SHIFT_PRESSED } { 'A' } else { 'a' },
-        0x1F => if unsafe { SHIFT_PRESSED } { 'S' } else { 's' },
-        0x20 => if unsafe { SHIFT_PRESSED } { 'D' } else { 'd' },
-        0x21 => if unsafe { SHIFT_PRESSED } { 'F' } else { 'f' },
-        0x22 => if unsafe { SHIFT_PRESSED } { 'G' } else { 'g' },
-        0x23 => if unsafe { SHIFT_PRESSED } { 'H' } else { 'h' },
-        0x24 => if unsafe { SHIFT_PRESSED } { 'J' } else { 'j' },
-        0x25 => if unsafe { SHIFT_PRESSED } { 'K' } else { 'k' },
-        0x26 => if unsafe { SHIFT_PRESSED } { 'L' } else { 'l' },
-        0x2C => if unsafe { SHIFT_PRESSED } { 'Z' } else { 'z' },
-        0x2D => if unsafe { SHIFT_PRESSED } { 'X' } else { 'x' },
-        0x2E => if unsafe { SHIFT_PRESSED } { 'C' } else { 'c' },
-        0x2F => if unsafe { SHIFT_PRESSED } { 'V' } else { 'v' },
-        0x30 => if unsafe { SHIFT_PRESSED } { 'B' } else { 'b' },
-        0x31 => if unsafe { SHIFT_PRESSED } { 'N' } else { 'n' },
-        0x32 => if unsafe { SHIFT_PRESSED } { 'M' } else { 'm' },
+        0x0E => '\u{8}',
         0x1C => '\n',
         0x39 => ' ',
-        0x0C => if unsafe { SHIFT_PRESSED } { '_' } else { '-' },
-        0x0D => if unsafe { SHIFT_PRESSED } { '+' } else { '=' },
-        0x1A => if unsafe { SHIFT_PRESSED } { '{' } else { '[' },
-        0x1B => if unsafe { SHIFT_PRESSED } { '}' } else { ']' },
-        0x27 => if unsafe { SHIFT_PRESSED } { ':' } else { ';' },
-        0x28 => if unsafe { SHIFT_PRESSED } { '"' } else { '\'' },
-        0x29 => if unsafe { SHIFT_PRESSED } { '~' } else { '`' },
-        0x2B => if unsafe { SHIFT_PRESSED } { '|' } else { '\\' },
-        0x33 => if unsafe { SHIFT_PRESSED } { '<' } else { ',' },
-        0x34 => if unsafe { SHIFT_PRESSED } { '>' } else { '.' },
-        0x35 => if unsafe { SHIFT_PRESSED } { '?' } else { '/' },
-        _ => '\0',
-    }
-}
\ No newline at end of file
+        _ => unsafe {
+            let keymap = keymap::active();
+            let character = keymap.lookup(scancode, SHIFT_PRESSED, ALTGR_PRESSED);
+            // Caps Lock only inverts Shift for letters.
+            if CAPS_LOCK && character.is_alphabetic() {
+                keymap.lookup(scancode, !SHIFT_PRESSED, ALTGR_PRESSED)
+            } else {
+                character
+            }
+        },
+    }
+}
+
+/// Returns `true` while Caps Lock is on.
+pub fn caps_lock() -> bool {
+    unsafe { CAPS_LOCK }
+}
+
+/// Returns `true` while Num Lock is on.
+pub fn num_lock() -> bool {
+    unsafe { NUM_LOCK }
+}
+
+/// Sets the keyboard LEDs to the lock states and shows them on the status row.
+fn update_leds() {
+    let leds = unsafe { (NUM_LOCK as u8) << 1 | (CAPS_LOCK as u8) << 2 };
+    if ps2::keyboard_command(0xED) {
+        ps2::keyboard_command(leds);
+    }
+    status::refresh();
+}
+
+fn apply_dead_key(character: char) -> char {
+    if character == '\0' {
+        return '\0';
+    }
+    unsafe {
+        let dead = DEAD_KEY;
+        if dead == '\0' {
+            if keymap::is_dead(character) {
+                DEAD_KEY = character;
+                return '\0';
+            }
+            return character;
+        }
+        DEAD_KEY = '\0';
+        if character == ' ' || character == dead {
+            return keymap::spacing(dead);
+        }
+        if keymap::is_dead(character) {
+            DEAD_KEY = character;
+            return keymap::spacing(dead);
+        }
+        let composed = keymap::compose(dead, character);
+        if composed != '\0' {
+            return composed;
+        }
+        PENDING[console::current()] = character;
+        keymap::spacing(dead)
+    }
+}
@@ -0,0 +1,253 @@
+/// One shift level of a keyboard layout.
+///
+/// Each string covers a contiguous run of scancode set 1 make codes. `'\0'` marks a key that
+/// produces nothing on this level and a combining diacritical mark (U+0300..=U+0308) marks a
+/// dead key that is composed with the next character.
+struct Level {
+    /// Scancodes 0x02..=0x0D (the number row).
+    digits: &'static str,
+    /// Scancodes 0x10..=0x1B (the top letter row).
+    top: &'static str,
+    /// Scancodes 0x1E..=0x29 (the home row plus the key left of `1`).
+    home: &'static str,
+    /// Scancodes 0x2B..=0x35 (the key above enter on ISO boards plus the bottom row).
+    bottom: &'static str,
+    /// Scancode 0x56 (the extra key next to left shift on ISO boards).
+    iso: char,
+}
+
+/// A keyboard layout with unshifted, shifted and AltGr (third level) characters.
+pub struct Keymap {
+    pub name: &'static str,
+    normal: Level,
+    shift: Level,
+    altgr: Level,
+}
+
+const GRAVE: char = '\u{300}';
+const ACUTE: char = '\u{301}';
+const CIRCUMFLEX: char = '\u{302}';
+const TILDE: char = '\u{303}';
+const DIAERESIS: char = '\u{308}';
+
+static US: Keymap = Keymap {
+    name: "us",
+    normal: Level {
+        digits: "1234567890-=",
+        top: "qwertyuiop[]",
+        home: "asdfghjkl;'`",
+        bottom: "\\zxcvbnm,./",
+        iso: '\\',
+    },
+    shift: Level {
+        digits: "!@#$%^&*()_+",
+        top: "QWERTYUIOP{}",
+        home: "ASDFGHJKL:\"~",
+        bottom: "|ZXCVBNM<>?",
+        iso: '|',
+    },
+    altgr: Level {
+        digits: "",
+        top: "",
+        home: "",
+        bottom: "",
+        iso: '\0',
+    },
+};
+
+static UK: Keymap = Keymap {
+    name: "uk",
+    normal: Level {
+        digits: "1234567890-=",
+        top: "qwertyuiop[]",
+        home: "asdfghjkl;'`",
+        bottom: "#zxcvbnm,./",
+        iso: '\\',
+    },
+    shift: Level {
+        digits: "!\"£$%^&*()_+",
+        top: "QWERTYUIOP{}",
+        home: "ASDFGHJKL:@¬",
+        bottom: "~ZXCVBNM<>?",
+        iso: '|',
+    },
+    altgr: Level {
+        digits: "",
+        top: "\0\0é\0\0\0úíó",
+        home: "á",
+        bottom: "",
+        iso: '\0',
+    },
+};
+
+static DE: Keymap = Keymap {
+    name: "de",
+    normal: Level {
+        digits: "1234567890ß\u{301}",
+        top: "qwertzuiopü+",
+        home: "asdfghjklöä\u{302}",
+        bottom: "#yxcvbnm,.-",
+        iso: '<',
+    },
+    shift: Level {
+        digits: "!\"§$%&/()=?\u{300}",
+        top: "QWERTZUIOPÜ*",
+        home: "ASDFGHJKLÖÄ°",
+        bottom: "'YXCVBNM;:_",
+        iso: '>',
+    },
+    altgr: Level {
+        digits: "\0²\0\0\0\0{[]}\\",
+        top: "@\0\0\0\0\0\0\0\0\0\0~",
+        home: "",
+        bottom: "\0\0\0\0\0\0\0µ",
+        iso: '|',
+    },
+};
+
+static FR: Keymap = Keymap {
+    name: "fr",
+    normal: Level {
+        digits: "&é\"'(-è_çà)=",
+        top: "azertyuiop\u{302}$",
+        home: "qsdfghjklmù²",
+        bottom: "*wxcvbn,;:!",
+        iso: '<',
+    },
+    shift: Level {
+        digits: "1234567890°+",
+        top: "AZERTYUIOP\u{308}£",
+        home: "QSDFGHJKLM%",
+        bottom: "µWXCVBN?./§",
+        iso: '>',
+    },
+    altgr: Level {
+        digits: "\0\u{303}#{[|\u{300}\\^@]}",
+        top: "",
+        home: "",
+        bottom: "",
+        iso: '\0',
+    },
+};
+
+static DVORAK: Keymap = Keymap {
+    name: "dvorak",
+    normal: Level {
+        digits: "1234567890[]",
+        top: "',.pyfgcrl/=",
+        home: "aoeuidhtns-`",
+        bottom: "\\;qjkxbmwvz",
+        iso: '\\',
+    },
+    shift: Level {
+        digits: "!@#$%^&*(){}",
+        top: "\"<>PYFGCRL?+",
+        home: "AOEUIDHTNS_~",
+        bottom: "|:QJKXBMWVZ",
+        iso: '|',
+    },
+    altgr: Level {
+        digits: "",
+        top: "",
+        home: "",
+        bottom: "",
+        iso: '\0',
+    },
+};
+
+/// All available layouts, selectable by name with `select`.
+pub static KEYMAPS: [&Keymap; 5] = [&US, &UK, &DE, &FR, &DVORAK];
+
+static mut ACTIVE: usize = 0;
+
+/// Returns the layout currently used to translate scancodes.
+pub fn active() -> &'static Keymap {
+    unsafe { KEYMAPS[ACTIVE] }
+}
+
+/// Makes the layout with the given name active. Returns `false` if there is no such layout.
+pub fn select(name: &str) -> bool {
+    match KEYMAPS.iter().position(|keymap| keymap.name == name) {
+        Some(index) => {
+            unsafe {
+                ACTIVE = index;
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+impl Keymap {
+    /// Translates a make code into a character, or `'\0'` if the key produces nothing.
+    pub fn lookup(&self, scancode: u8, shift: bool, altgr: bool) -> char {
+        let level = if altgr {
+            &self.altgr
+        } else if shift {
+            &self.shift
+        } else {
+            &self.normal
+        };
+        let (row, first) = match scancode {
+            0x02..=0x0D => (level.digits, 0x02),
+            0x10..=0x1B => (level.top, 0x10),
+            0x1E..=0x29 => (level.home, 0x1E),
+            0x2B..=0x35 => (level.bottom, 0x2B),
+            0x56 => return level.iso,
+            _ => return '\0',
+        };
+        row.chars().nth((scancode - first) as usize).unwrap_or('\0')
+    }
+}
+
+/// Returns `true` if the character marks a dead key.
+pub fn is_dead(character: char) -> bool {
+    matches!(character, GRAVE | ACUTE | CIRCUMFLEX | TILDE | DIAERESIS)
+}
+
+/// Returns the standalone character a dead key produces when followed by space.
+pub fn spacing(dead: char) -> char {
+    match dead {
+        GRAVE => '`',
+        ACUTE => '\'',
+        CIRCUMFLEX => '^',
+        TILDE => '~',
+        DIAERESIS => '"',
+        _ => '\0',
+    }
+}
+
+/// Combines a dead key with a base character. Only results that exist in code page 437 are
+/// produced; anything else returns `'\0'`.
+pub fn compose(dead: char, base: char) -> char {
+    match (dead, base) {
+        (GRAVE, 'a') => 'à',
+        (GRAVE, 'e') => 'è',
+        (GRAVE, 'i') => 'ì',
+        (GRAVE, 'o') => 'ò',
+        (GRAVE, 'u') => 'ù',
+        (ACUTE, 'a') => 'á',
+        (ACUTE, 'e') => 'é',
+        (ACUTE, 'i') => 'í',
+        (ACUTE, 'o') => 'ó',
+        (ACUTE, 'u') => 'ú',
+        (ACUTE, 'E') => 'É',
+        (CIRCUMFLEX, 'a') => 'â',
+        (CIRCUMFLEX, 'e') => 'ê',
+        (CIRCUMFLEX, 'i') => 'î',
+        (CIRCUMFLEX, 'o') => 'ô',
+        (CIRCUMFLEX, 'u') => 'û',
+        (TILDE, 'n') => 'ñ',
+        (TILDE, 'N') => 'Ñ',
+        (DIAERESIS, 'a') => 'ä',
+        (DIAERESIS, 'e') => 'ë',
+        (DIAERESIS, 'i') => 'ï',
+        (DIAERESIS, 'o') => 'ö',
+        (DIAERESIS, 'u') => 'ü',
+        (DIAERESIS, 'y') => 'ÿ',
+        (DIAERESIS, 'A') => 'Ä',
+        (DIAERESIS, 'O') => 'Ö',
+        (DIAERESIS, 'U') => 'Ü',
+        _ => '\0',
+    }
+}
@@ -2,24 +2,54 @@
 #![no_main]
 
 use core::panic::PanicInfo;
+use bootloader::{entry_point, BootInfo};
 
+mod vga;
 mod vga_buffer;
+mod ata;
+mod console;
+mod cp437;
+mod ext2;
+mod fat;
+mod fs;
+mod gfx;
 mod keyboard_buffer;
+mod keymap;
+mod memory;
+mod mouse;
+mod pci;
+mod ps2;
+mod ramfs;
+mod screenshot;
+mod serial;
 mod shell;
+mod status;
+mod store;
+mod sysrq;
+mod theme;
+mod vbe;
 mod commands;
 mod configuration;
 
 pub static mut ERROR: bool = false;
-static mut FILE: [u8; 1920] = [0; 1920];
-static mut LENGTH: usize = 0;
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
 }
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+entry_point!(kernel_main);
+
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    memory::init(boot_info);
+    vga_buffer::init();
+    serial::init();
+    ata::init();
+    fs::init();
+    store::init();
+    ps2::init();
+    mouse::init();
+    status::init();
     println!("Booted the h-3x kernel successfully");
     shell::shell();
     loop {}
@@ -40,99 +70,104 @@ pub fn system_call(function: i32, input: &[u8]) {
     }
 }
 
+/// The file holding the text of the former variable.
+const VARIABLE: &str = "/variable";
+
 fn ls() {
-    unsafe {
-        for i in 0..LENGTH {
-            print!("{}", FILE[i] as char);
+    let Ok(fd) = fs::open(VARIABLE, fs::READ | fs::CREATE) else { return };
+    let mut buffer = [0; 512];
+    while let Ok(count @ 1..) = fs::read(fd, &mut buffer) {
+        for &byte in &buffer[..count] {
+            print!("{}", byte as char);
         }
     }
+    fs::close(fd);
     println!();
 }
 
 fn purge() {
-    unsafe {
-        FILE = [0; 1920];
-        LENGTH = 0;
+    if let Ok(fd) = fs::open(VARIABLE, fs::WRITE | fs::CREATE | fs::TRUNCATE) {
+        fs::close(fd);
     }
 }
 
-fn rm(text: &[u8]) {
-    unsafe {
-        if !text.is_empty() {
-            if let Ok(current) = core::str::from_utf8(&FILE[..LENGTH]) {
-                let mut new_content: [u8; 1920] = [0; 1920];
-                let mut new_index = 0;
-                let mut found = false;
-                let name = core::str::from_utf8(text).unwrap_or("");
-                for token in current.split_whitespace() {
-                    if !found && token == name {
-                        found = true;
-                        continue;
-                    }
-                    if token.len() > 0 {
-                        if new_index != 0 {
-                            if new_index < new_content.len() {
-                                new_content[new_index] = b' ';
-                                new_index += 1;
-                            }
-                        }
-                        for &b in token.as_bytes() {
-                            if new_index < new_content.len() {
-                                new_content[new_index] = b;
-                                new_index += 1;
-                            }
-                        }
-                    }
-                }
-                if new_index > 0 && new_content[new_index - 1] != b' ' {
-                    if new_index < new_content.len() {
-                        new_content[new_index] = b' ';
-                        new_index += 1;
-                    }
-                }
-                if !found {
-                    ERROR = true;
-                    println!("ERROR: Text not found");
-                } else {
-                    ERROR = false;
-                    for i in 0..new_index {
-                        FILE[i] = new_content[i];
-                    }
-                    LENGTH = new_index;
+/// Returns the offset of the first whitespace-separated token of an open file equal to `name`.
+fn find_token(fd: usize, name: &[u8]) -> Option<usize> {
+    let mut buffer = [0; 512];
+    let mut offset = 0;
+    let mut start = 0;
+    let mut matched = Some(0);
+    loop {
+        let count = fs::read(fd, &mut buffer).unwrap_or(0);
+        for (index, &byte) in buffer[..count].iter().enumerate() {
+            if byte.is_ascii_whitespace() {
+                if matched == Some(name.len()) {
+                    return Some(start);
                 }
+                matched = Some(0);
+                start = offset + index + 1;
+            } else {
+                matched = matched.filter(|&length| name.get(length) == Some(&byte)).map(|length| length + 1);
             }
         }
-        else {
+        if count == 0 {
+            return if matched == Some(name.len()) { Some(start) } else { None };
+        }
+        offset += count;
+    }
+}
+
+fn rm(text: &[u8]) {
+    unsafe {
+        if text.is_empty() {
             ERROR = true;
             println!("ERROR: No text provided");
+            return;
         }
+        let Ok(fd) = fs::open(VARIABLE, fs::READ | fs::WRITE | fs::CREATE) else { return };
+        let Some(start) = find_token(fd, text) else {
+            fs::close(fd);
+            ERROR = true;
+            println!("ERROR: Text not found");
+            return;
+        };
+        ERROR = false;
+        // Drops the token and the space after it by moving the rest of the file down.
+        let size = fs::seek(fd, fs::SeekFrom::End(0)).unwrap_or(0);
+        let gap = (start + text.len() + 1).min(size) - start;
+        let _ = fs::seek(fd, fs::SeekFrom::Start(start + gap));
+        let mut buffer = [0; 512];
+        loop {
+            let count = fs::read(fd, &mut buffer).unwrap_or(0);
+            if count == 0 {
+                break;
+            }
+            let _ = fs::seek(fd, fs::SeekFrom::Current(-((gap + count) as isize)));
+            let _ = fs::write(fd, &buffer[..count]);
+            let _ = fs::seek(fd, fs::SeekFrom::Current(gap as isize));
+        }
+        let _ = fs::truncate(fd, size - gap);
+        fs::close(fd);
     }
 }
 
 fn touch(text: &[u8]) {
     unsafe {
-        if !text.is_empty() {
-            let space: usize = text.len();
-            if LENGTH + space > FILE.len() {
-                ERROR = true;
-                println!("ERROR: Memory attempted to exceed 1920 bytes");
-                return;
-            }
-            else {
-                ERROR = false;
-            }
-            for &byte in text {
-                FILE[LENGTH] = byte;
-                LENGTH += 1;
-            }
-            if LENGTH < FILE.len() {
-                FILE[LENGTH] = b' ';
-                LENGTH += 1;
-            }
-        }
-        else {
+        if text.is_empty() {
             ERROR = true;
             println!("ERROR: No text provided");
+            return;
+        }
+        let Ok(fd) = fs::open(VARIABLE, fs::APPEND | fs::CREATE) else { return };
+        let size = fs::seek(fd, fs::SeekFrom::End(0)).unwrap_or(0);
+        match fs::write(fd, text).and_then(|_| fs::write(fd, b" ")) {
+            Ok(_) => ERROR = false,
+            Err(message) => {
+                let _ = fs::truncate(fd, size);
+                ERROR = true;
+                println!("ERROR: {}", message);
+            }
         }
+        fs::close(fd);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,86 @@
+use bootloader::bootinfo::{BootInfo, MemoryRegionType};
+use spin::Mutex;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate};
+use x86_64::{PhysAddr, VirtAddr};
+
+static mut BOOT_INFO: Option<&'static BootInfo> = None;
+
+/// Keeps the boot information handed over by the bootloader for later queries.
+pub fn init(boot_info: &'static BootInfo) {
+    unsafe {
+        BOOT_INFO = Some(boot_info);
+    }
+}
+
+/// Returns the total size of the memory regions of the given type, in bytes.
+pub fn total(region_type: MemoryRegionType) -> u64 {
+    let boot_info = match unsafe { BOOT_INFO } {
+        Some(boot_info) => boot_info,
+        None => return 0,
+    };
+    boot_info
+        .memory_map
+        .iter()
+        .filter(|region| region.region_type == region_type)
+        .map(|region| region.range.end_addr() - region.range.start_addr())
+        .sum()
+}
+
+/// Returns the number of entries in the bootloader's memory map.
+pub fn regions() -> usize {
+    match unsafe { BOOT_INFO } {
+        Some(boot_info) => boot_info.memory_map.iter().count(),
+        None => 0,
+    }
+}
+
+/// Hands out the usable frames of the memory map in order; frames are never freed.
+struct FrameAllocator {
+    /// The memory map entry the next frame is taken from.
+    region: usize,
+    /// The next frame number within that entry, or below it when the entry was just reached.
+    frame: u64,
+}
+
+static FRAMES: Mutex<FrameAllocator> = Mutex::new(FrameAllocator { region: 0, frame: 0 });
+
+unsafe impl x86_64::structures::paging::FrameAllocator<Size4KiB> for FrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let boot_info = unsafe { BOOT_INFO }?;
+        while let Some(region) = boot_info.memory_map.get(self.region) {
+            let frame = self.frame.max(region.range.start_frame_number);
+            if region.region_type == MemoryRegionType::Usable && frame < region.range.end_frame_number {
+                self.frame = frame + 1;
+                return Some(PhysFrame::containing_address(PhysAddr::new(frame * Size4KiB::SIZE)));
+            }
+            self.region += 1;
+            self.frame = 0;
+        }
+        None
+    }
+}
+
+/// Makes `size` bytes of physical memory starting at `address`, such as a device's memory,
+/// accessible and returns where. Uncached mappings are added to the bootloader's mapping of
+/// physical memory for any page it does not already cover.
+pub fn map_physical(address: u64, size: u64) -> Option<*mut u8> {
+    let boot_info = unsafe { BOOT_INFO }?;
+    let offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let (level_4_frame, _) = Cr3::read();
+    let level_4_table = unsafe { &mut *((offset + level_4_frame.start_address().as_u64()).as_mut_ptr::<PageTable>()) };
+    let mut mapper = unsafe { OffsetPageTable::new(level_4_table, offset) };
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    let mut frames = FRAMES.lock();
+    let start = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(address));
+    let end = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(address + size - 1));
+    for frame in PhysFrame::range_inclusive(start, end) {
+        let page = Page::containing_address(offset + frame.start_address().as_u64());
+        if mapper.translate_addr(page.start_address()) == Some(frame.start_address()) {
+            continue;
+        }
+        unsafe { mapper.map_to(page, frame, flags, &mut *frames) }.ok()?.flush();
+    }
+    Some((offset + address).as_mut_ptr())
+}
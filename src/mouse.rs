@@ -0,0 +1,203 @@
+use spin::Mutex;
+use crate::{cp437, ps2};
+use crate::vga_buffer::{self, WRITER, BUFFER_HEIGHT, BUFFER_WIDTH};
+
+pub const LEFT_BUTTON: u8 = 0x01;
+pub const RIGHT_BUTTON: u8 = 0x02;
+pub const MIDDLE_BUTTON: u8 = 0x04;
+
+/// Mouse counts per text cell horizontally and vertically.
+const CELL_WIDTH: i32 = 8;
+const CELL_HEIGHT: i32 = 16;
+
+/// Lines scrolled through the history per wheel notch.
+const WHEEL_LINES: isize = 3;
+
+const EVENT_QUEUE_SIZE: usize = 16;
+const CLIPBOARD_SIZE: usize = BUFFER_WIDTH * BUFFER_HEIGHT + BUFFER_HEIGHT;
+
+/// A decoded mouse packet, in text cell coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct MouseEvent {
+    pub row: usize,
+    pub col: usize,
+    /// Bitmask of `LEFT_BUTTON`, `RIGHT_BUTTON` and `MIDDLE_BUTTON`.
+    pub buttons: u8,
+    /// Wheel movement; negative values scroll up.
+    pub wheel: i8,
+}
+
+struct Mouse {
+    present: bool,
+    packet: [u8; 4],
+    packet_size: usize,
+    index: usize,
+    x: i32,
+    y: i32,
+    buttons: u8,
+    anchor: Option<usize>,
+    events: [MouseEvent; EVENT_QUEUE_SIZE],
+    head: usize,
+    count: usize,
+    clipboard: [u8; CLIPBOARD_SIZE],
+    clipboard_length: usize,
+    paste_position: usize,
+}
+
+static MOUSE: Mutex<Mouse> = Mutex::new(Mouse {
+    present: false,
+    packet: [0; 4],
+    packet_size: 3,
+    index: 0,
+    x: 0,
+    y: 0,
+    buttons: 0,
+    anchor: None,
+    events: [MouseEvent { row: 0, col: 0, buttons: 0, wheel: 0 }; EVENT_QUEUE_SIZE],
+    head: 0,
+    count: 0,
+    clipboard: [0; CLIPBOARD_SIZE],
+    clipboard_length: 0,
+    paste_position: CLIPBOARD_SIZE,
+});
+
+fn set_sample_rate(rate: u8) -> bool {
+    ps2::aux_command(0xF3) && ps2::aux_command(rate)
+}
+
+/// Resets the mouse on the auxiliary port and turns on data reporting.
+///
+/// The IntelliMouse sample rate sequence (200, 100, 80) is tried first; a mouse that then
+/// reports ID 3 sends 4-byte packets carrying wheel movement.
+pub fn init() {
+    if !ps2::dual_channel() {
+        return;
+    }
+    if !ps2::aux_command(0xFF) || ps2::read_data() != Some(0xAA) {
+        return;
+    }
+    ps2::read_data();
+    if !ps2::aux_command(0xF6) {
+        return;
+    }
+    let mut packet_size = 3;
+    if set_sample_rate(200) && set_sample_rate(100) && set_sample_rate(80) && ps2::aux_command(0xF2) && ps2::read_data() == Some(3) {
+        packet_size = 4;
+    }
+    if !ps2::aux_command(0xF4) {
+        return;
+    }
+    let mut mouse = MOUSE.lock();
+    mouse.present = true;
+    mouse.packet_size = packet_size;
+    mouse.x = vga_buffer::width() as i32 * CELL_WIDTH / 2;
+    mouse.y = vga_buffer::height() as i32 * CELL_HEIGHT / 2;
+}
+
+/// Returns `true` if a mouse was found and enabled by `init`.
+pub fn present() -> bool {
+    MOUSE.lock().present
+}
+
+/// Feeds one byte read from the auxiliary port into the packet decoder.
+///
+/// Called by `keyboard_buffer::read_char` whenever the controller reports auxiliary data, so
+/// the pointer and selection are handled no matter which command is reading input.
+pub fn handle_byte(byte: u8) {
+    let mut mouse = MOUSE.lock();
+    // Bit 3 of the first byte is always set; resynchronize on anything else.
+    if mouse.index == 0 && byte & 0x08 == 0 {
+        return;
+    }
+    let index = mouse.index;
+    mouse.packet[index] = byte;
+    mouse.index += 1;
+    if mouse.index < mouse.packet_size {
+        return;
+    }
+    mouse.index = 0;
+
+    let flags = mouse.packet[0];
+    if flags & 0xC0 != 0 {
+        return;
+    }
+    let dx = mouse.packet[1] as i32 - ((flags as i32) << 4 & 0x100);
+    let dy = mouse.packet[2] as i32 - ((flags as i32) << 3 & 0x100);
+    let wheel = if mouse.packet_size == 4 { mouse.packet[3] as i8 } else { 0 };
+    let buttons = flags & 0x07;
+
+    mouse.x = (mouse.x + dx).clamp(0, vga_buffer::width() as i32 * CELL_WIDTH - 1);
+    mouse.y = (mouse.y - dy).clamp(0, vga_buffer::height() as i32 * CELL_HEIGHT - 1);
+    let col = (mouse.x / CELL_WIDTH) as usize;
+    let row = (mouse.y / CELL_HEIGHT) as usize;
+    let cell = row * BUFFER_WIDTH + col;
+
+    let pressed = buttons & !mouse.buttons;
+    let released = mouse.buttons & !buttons;
+    mouse.buttons = buttons;
+
+    let mut writer = WRITER.lock();
+    if wheel != 0 {
+        writer.scroll_view(-(wheel as isize) * WHEEL_LINES);
+    }
+    writer.set_pointer(Some((row, col)));
+    if pressed & LEFT_BUTTON != 0 {
+        mouse.anchor = Some(cell);
+        writer.set_selection(Some((cell, cell)));
+    } else if let Some(anchor) = mouse.anchor {
+        if released & LEFT_BUTTON != 0 {
+            let (start, end) = if anchor <= cell { (anchor, cell) } else { (cell, anchor) };
+            // A click without dragging keeps the previous clipboard contents.
+            if start != end {
+                let mouse = &mut *mouse;
+                mouse.clipboard_length = writer.copy_text(start, end, &mut mouse.clipboard);
+            }
+            mouse.anchor = None;
+            writer.set_selection(None);
+        } else {
+            writer.set_selection(Some((anchor, cell)));
+        }
+    }
+    drop(writer);
+    if pressed & (MIDDLE_BUTTON | RIGHT_BUTTON) != 0 {
+        mouse.paste_position = 0;
+    }
+
+    let slot = (mouse.head + mouse.count) % EVENT_QUEUE_SIZE;
+    mouse.events[slot] = MouseEvent { row, col, buttons, wheel };
+    if mouse.count < EVENT_QUEUE_SIZE {
+        mouse.count += 1;
+    } else {
+        mouse.head = (mouse.head + 1) % EVENT_QUEUE_SIZE;
+    }
+}
+
+/// Removes and returns the oldest queued mouse event.
+///
+/// Events are queued while input is read through `keyboard_buffer::read_char`, so an
+/// application should drain this queue after each call to it.
+pub fn poll_event() -> Option<MouseEvent> {
+    let mut mouse = MOUSE.lock();
+    if mouse.count == 0 {
+        return None;
+    }
+    let event = mouse.events[mouse.head];
+    mouse.head = (mouse.head + 1) % EVENT_QUEUE_SIZE;
+    mouse.count -= 1;
+    Some(event)
+}
+
+/// Returns the next character of a paste started with the middle or right button, or `'\0'`
+/// if nothing is being pasted.
+pub fn take_paste() -> char {
+    let mut mouse = MOUSE.lock();
+    if mouse.paste_position >= mouse.clipboard_length {
+        return '\0';
+    }
+    let character = match mouse.clipboard[mouse.paste_position] {
+        b'\n' => '\n',
+        byte => cp437::decode(byte),
+    };
+    mouse.paste_position += 1;
+    character
+}
@@ -0,0 +1,66 @@
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+/// The bus, device and function numbers of a PCI function.
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+/// Reads a 32-bit register from the configuration space of a function through the legacy
+/// 0xCF8/0xCFC mechanism.
+pub fn read_config(location: Location, offset: u8) -> u32 {
+    let address = 0x8000_0000
+        | (location.bus as u32) << 16
+        | (location.device as u32) << 11
+        | (location.function as u32) << 8
+        | (offset & 0xFC) as u32;
+    let mut address_port: Port<u32> = Port::new(CONFIG_ADDRESS);
+    let mut data_port: Port<u32> = Port::new(CONFIG_DATA);
+    unsafe {
+        address_port.write(address);
+        data_port.read()
+    }
+}
+
+/// Returns the first function with the given vendor and device IDs, scanning every bus.
+pub fn find(vendor: u16, device: u16) -> Option<Location> {
+    for bus in 0..=255u8 {
+        for slot in 0..32u8 {
+            for function in 0..8u8 {
+                let location = Location { bus, device: slot, function };
+                let id = read_config(location, 0x00);
+                if id & 0xFFFF == 0xFFFF {
+                    // no function 0 means an empty slot
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+                if id == (device as u32) << 16 | vendor as u32 {
+                    return Some(location);
+                }
+                // bit 7 of the header type marks multi-function devices
+                if function == 0 && read_config(location, 0x0C) & 0x0080_0000 == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Returns the physical address of a memory base address register (0-5).
+pub fn bar_address(location: Location, bar: u8) -> u64 {
+    let low = read_config(location, 0x10 + bar * 4);
+    let mut address = (low & 0xFFFF_FFF0) as u64;
+    // type 2 in bits 1-2 is a 64-bit BAR continued in the next register
+    if low & 0x06 == 0x04 {
+        address |= (read_config(location, 0x14 + bar * 4) as u64) << 32;
+    }
+    address
+}
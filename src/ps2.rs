@@ -0,0 +1,220 @@
+use x86_64::instructions::port::Port;
+use crate::println;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 0x01;
+const STATUS_INPUT_FULL: u8 = 0x02;
+
+const CONFIG_PORT1_IRQ: u8 = 0x01;
+const CONFIG_PORT2_IRQ: u8 = 0x02;
+const CONFIG_PORT2_CLOCK_DISABLED: u8 = 0x20;
+const CONFIG_TRANSLATION: u8 = 0x40;
+
+const ACK: u8 = 0xFA;
+const RESEND: u8 = 0xFE;
+
+/// Number of status polls before a controller or device is considered unresponsive.
+const TIMEOUT: usize = 1_000_000;
+
+/// Typematic repeat rates in tenths of characters per second, indexed by the rate bits.
+const RATES: [u16; 32] = [
+    300, 267, 240, 218, 207, 185, 171, 160, 150, 133, 120, 109, 100, 92, 86, 80,
+    75, 67, 60, 55, 50, 46, 43, 40, 37, 33, 30, 27, 25, 23, 21, 20,
+];
+
+/// Typematic delays in milliseconds, indexed by the delay bits.
+pub const DELAYS: [u16; 4] = [250, 500, 750, 1000];
+
+static mut DUAL_CHANNEL: bool = false;
+
+fn status() -> u8 {
+    let mut port: Port<u8> = Port::new(STATUS_PORT);
+    unsafe { port.read() }
+}
+
+fn wait_input_empty() -> bool {
+    for _ in 0..TIMEOUT {
+        if status() & STATUS_INPUT_FULL == 0 {
+            return true;
+        }
+    }
+    false
+}
+
+fn wait_output_full() -> bool {
+    for _ in 0..TIMEOUT {
+        if status() & STATUS_OUTPUT_FULL != 0 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Sends a command byte to the controller itself.
+pub fn command(byte: u8) {
+    let mut port: Port<u8> = Port::new(COMMAND_PORT);
+    if wait_input_empty() {
+        unsafe { port.write(byte) }
+    }
+}
+
+/// Writes a byte to the data port, which goes to the first device unless redirected.
+pub fn write_data(byte: u8) -> bool {
+    let mut port: Port<u8> = Port::new(DATA_PORT);
+    if !wait_input_empty() {
+        return false;
+    }
+    unsafe { port.write(byte) }
+    true
+}
+
+/// Reads a byte from the data port, giving up after `TIMEOUT` polls.
+pub fn read_data() -> Option<u8> {
+    let mut port: Port<u8> = Port::new(DATA_PORT);
+    if wait_output_full() {
+        Some(unsafe { port.read() })
+    } else {
+        None
+    }
+}
+
+fn flush() {
+    let mut port: Port<u8> = Port::new(DATA_PORT);
+    while status() & STATUS_OUTPUT_FULL != 0 {
+        unsafe { port.read(); }
+    }
+}
+
+fn read_config() -> u8 {
+    command(0x20);
+    read_data().unwrap_or(0)
+}
+
+fn write_config(config: u8) {
+    command(0x60);
+    write_data(config);
+}
+
+/// Sends a byte to the keyboard and waits for it to be acknowledged, resending if asked to.
+pub fn keyboard_command(byte: u8) -> bool {
+    for _ in 0..3 {
+        if !write_data(byte) {
+            return false;
+        }
+        match read_data() {
+            Some(ACK) => return true,
+            Some(RESEND) => continue,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Sends a byte to the device on the second port and waits for it to be acknowledged.
+pub fn aux_command(byte: u8) -> bool {
+    for _ in 0..3 {
+        command(0xD4);
+        if !write_data(byte) {
+            return false;
+        }
+        match read_data() {
+            Some(ACK) => return true,
+            Some(RESEND) => continue,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Returns `true` if the controller reported a second (auxiliary) port during `init`.
+pub fn dual_channel() -> bool {
+    unsafe { DUAL_CHANNEL }
+}
+
+/// Brings the 8042 controller and the keyboard into a known state.
+///
+/// Disables both ports, flushes stale output, runs the controller and port self-tests,
+/// detects the second port and then re-enables the keyboard with scanning turned on. The
+/// kernel polls the controller, so device interrupts are left disabled while translation to
+/// scancode set 1 stays on for `keyboard_buffer`. If the controller fails its self-test, the
+/// configuration left by the firmware is restored and the keyboard port re-enabled, so the
+/// keyboard keeps working as it did before.
+pub fn init() {
+    command(0xAD);
+    command(0xA7);
+    flush();
+
+    let firmware = read_config();
+    let mut config = firmware;
+    config &= !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ);
+    config |= CONFIG_TRANSLATION;
+    write_config(config);
+
+    command(0xAA);
+    if read_data() != Some(0x55) {
+        println!("ERROR: PS/2 controller self-test failed");
+        write_config(firmware);
+        command(0xAE);
+        flush();
+        return;
+    }
+    // Some controllers reset themselves during the self-test.
+    write_config(config);
+
+    let mut dual = false;
+    if config & CONFIG_PORT2_CLOCK_DISABLED != 0 {
+        command(0xA8);
+        dual = read_config() & CONFIG_PORT2_CLOCK_DISABLED == 0;
+        command(0xA7);
+    }
+
+    command(0xAB);
+    let port1 = read_data() == Some(0x00);
+    if dual {
+        command(0xA9);
+        dual = read_data() == Some(0x00);
+    }
+    unsafe {
+        DUAL_CHANNEL = dual;
+    }
+
+    if !port1 {
+        println!("ERROR: PS/2 keyboard port failed its interface test");
+        return;
+    }
+    command(0xAE);
+    if dual {
+        command(0xA8);
+    }
+
+    if !keyboard_command(0xFF) || read_data() != Some(0xAA) {
+        println!("ERROR: PS/2 keyboard reset failed");
+    }
+    if !keyboard_command(0xF4) {
+        println!("ERROR: PS/2 keyboard did not enable scanning");
+    }
+    flush();
+}
+
+/// Programs the keyboard's typematic delay (in milliseconds) and repeat rate (in characters
+/// per second), picking the closest supported rate. Returns the rate actually selected in
+/// tenths of characters per second.
+pub fn set_typematic(delay: u16, rate: u16) -> Option<u16> {
+    let delay_bits = DELAYS.iter().position(|&d| d == delay)? as u8;
+    let tenths = rate * 10;
+    let mut rate_bits = 0;
+    for (index, &candidate) in RATES.iter().enumerate() {
+        if candidate.abs_diff(tenths) < RATES[rate_bits].abs_diff(tenths) {
+            rate_bits = index;
+        }
+    }
+    let byte = delay_bits << 5 | rate_bits as u8;
+    if keyboard_command(0xF3) && keyboard_command(byte) {
+        Some(RATES[rate_bits])
+    } else {
+        None
+    }
+}
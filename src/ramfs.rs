@@ -0,0 +1,354 @@
+use spin::Mutex;
+use crate::fs::{FileSystem, Stat};
+
+/// The number of files and directories of a filesystem, including its root directory.
+pub const NODES: usize = 256;
+pub const NAME_LENGTH: usize = 32;
+pub const BLOCK_SIZE: usize = 512;
+
+const ROOT: usize = 0;
+/// Marks the last block of a file in `next`; free blocks have 0.
+const END: u16 = u16::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    used: bool,
+    directory: bool,
+    parent: usize,
+    name: [u8; NAME_LENGTH],
+    name_length: usize,
+    /// First data block, or 0 if the file is empty.
+    first: u16,
+    size: usize,
+}
+
+impl Node {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_length]).unwrap_or("")
+    }
+}
+
+const EMPTY: Node = Node {
+    used: false,
+    directory: false,
+    parent: ROOT,
+    name: [0; NAME_LENGTH],
+    name_length: 0,
+    first: 0,
+    size: 0,
+};
+
+/// Files are chains of fixed-size blocks linked through `next`, so a file can grow until the
+/// block pool runs out. Block 0 is never allocated so that it can mean "no data".
+struct Inner<const BLOCKS: usize> {
+    mounted: bool,
+    nodes: [Node; NODES],
+    next: [u16; BLOCKS],
+    data: [[u8; BLOCK_SIZE]; BLOCKS],
+}
+
+/// A filesystem kept in memory, lost on reboot.
+pub struct RamFs<const BLOCKS: usize> {
+    inner: Mutex<Inner<BLOCKS>>,
+}
+
+/// The filesystem mounted on `/` at boot, 1 MiB.
+pub static ROOT_FS: RamFs<2048> = RamFs::new();
+
+/// Smaller filesystems handed out by `mount ramfs [path]`, 128 KiB each.
+static SPARES: [RamFs<256>; 3] = [const { RamFs::new() }; 3];
+
+/// Returns an unused spare filesystem, emptied, for the mount table.
+pub fn instance(_device: &str) -> Result<&'static dyn FileSystem, &'static str> {
+    for spare in SPARES.iter() {
+        if !spare.inner.lock().mounted {
+            spare.format();
+            return Ok(spare);
+        }
+    }
+    Err("All RAM filesystems are mounted")
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && name.len() <= NAME_LENGTH && !name.contains('/')
+}
+
+impl<const BLOCKS: usize> RamFs<BLOCKS> {
+    const fn new() -> Self {
+        RamFs {
+            inner: Mutex::new(Inner {
+                mounted: false,
+                nodes: [EMPTY; NODES],
+                next: [0; BLOCKS],
+                data: [[0; BLOCK_SIZE]; BLOCKS],
+            }),
+        }
+    }
+
+    /// Deletes everything and marks the filesystem as mounted.
+    pub fn format(&self) {
+        let mut inner = self.inner.lock();
+        inner.mounted = true;
+        inner.nodes = [EMPTY; NODES];
+        inner.next = [0; BLOCKS];
+        inner.nodes[ROOT].used = true;
+        inner.nodes[ROOT].directory = true;
+    }
+}
+
+impl<const BLOCKS: usize> Inner<BLOCKS> {
+    fn node(&self, id: usize) -> Result<&Node, &'static str> {
+        match self.nodes.get(id) {
+            Some(node) if node.used => Ok(node),
+            _ => Err("Stale file handle"),
+        }
+    }
+
+    fn directory(&self, id: usize) -> Result<&Node, &'static str> {
+        let node = self.node(id)?;
+        if !node.directory {
+            return Err("Not a directory");
+        }
+        Ok(node)
+    }
+
+    fn child(&self, directory: usize, name: &str) -> Option<usize> {
+        (1..NODES).find(|&id| {
+            let node = &self.nodes[id];
+            node.used && node.parent == directory && node.name() == name
+        })
+    }
+
+    fn is_empty(&self, directory: usize) -> bool {
+        !(1..NODES).any(|id| self.nodes[id].used && self.nodes[id].parent == directory)
+    }
+
+    /// Returns `true` if `id` is `ancestor` or inside it.
+    fn within(&self, mut id: usize, ancestor: usize) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            if id == ROOT {
+                return false;
+            }
+            id = self.nodes[id].parent;
+        }
+    }
+
+    fn free_blocks(&self) -> usize {
+        self.next[1..].iter().filter(|&&next| next == 0).count()
+    }
+
+    fn allocate(&mut self) -> u16 {
+        let block = (1..BLOCKS).find(|&block| self.next[block] == 0).unwrap();
+        self.next[block] = END;
+        self.data[block] = [0; BLOCK_SIZE];
+        block as u16
+    }
+
+    fn free_chain(&mut self, mut block: u16) {
+        while block != 0 && block != END {
+            let next = self.next[block as usize];
+            self.next[block as usize] = 0;
+            block = next;
+        }
+    }
+
+    /// Returns the `index`th block of a file, which must exist.
+    fn nth_block(&self, id: usize, index: usize) -> usize {
+        let mut block = self.nodes[id].first as usize;
+        for _ in 0..index {
+            block = self.next[block] as usize;
+        }
+        block
+    }
+
+    /// Grows or shrinks a file to `size` bytes; new bytes read as zero.
+    fn resize(&mut self, id: usize, size: usize) -> Result<(), &'static str> {
+        if self.node(id)?.directory {
+            return Err("Is a directory");
+        }
+        let old = self.nodes[id].size;
+        let (have, want) = (old.div_ceil(BLOCK_SIZE), size.div_ceil(BLOCK_SIZE));
+        if want > have {
+            if want - have > self.free_blocks() {
+                return Err("No space left");
+            }
+            let mut last = if have == 0 { 0 } else { self.nth_block(id, have - 1) };
+            for _ in have..want {
+                let block = self.allocate();
+                if last == 0 {
+                    self.nodes[id].first = block;
+                } else {
+                    self.next[last] = block;
+                }
+                last = block as usize;
+            }
+        } else if want < have {
+            if want == 0 {
+                let first = self.nodes[id].first;
+                self.free_chain(first);
+                self.nodes[id].first = 0;
+            } else {
+                let last = self.nth_block(id, want - 1);
+                let rest = self.next[last];
+                self.free_chain(rest);
+                self.next[last] = END;
+            }
+        }
+        if size > old && !old.is_multiple_of(BLOCK_SIZE) {
+            let block = self.nth_block(id, old / BLOCK_SIZE);
+            let end = (size - old / BLOCK_SIZE * BLOCK_SIZE).min(BLOCK_SIZE);
+            self.data[block][old % BLOCK_SIZE..end].fill(0);
+        }
+        self.nodes[id].size = size;
+        Ok(())
+    }
+}
+
+impl<const BLOCKS: usize> FileSystem for RamFs<BLOCKS> {
+    fn kind(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> usize {
+        ROOT
+    }
+
+    fn lookup(&self, directory: usize, name: &str) -> Result<usize, &'static str> {
+        let inner = self.inner.lock();
+        inner.directory(directory)?;
+        inner.child(directory, name).ok_or("No such file or directory")
+    }
+
+    fn create(&self, directory: usize, name: &str, is_directory: bool) -> Result<usize, &'static str> {
+        let mut inner = self.inner.lock();
+        inner.directory(directory)?;
+        if !valid_name(name) {
+            return Err("Invalid name");
+        }
+        if inner.child(directory, name).is_some() {
+            return Err("File exists");
+        }
+        let id = (1..NODES).find(|&id| !inner.nodes[id].used).ok_or("Too many files")?;
+        let node = &mut inner.nodes[id];
+        *node = EMPTY;
+        node.used = true;
+        node.directory = is_directory;
+        node.parent = directory;
+        node.name[..name.len()].copy_from_slice(name.as_bytes());
+        node.name_length = name.len();
+        Ok(id)
+    }
+
+    fn read(&self, inode: usize, offset: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let inner = self.inner.lock();
+        let node = inner.node(inode)?;
+        if node.directory {
+            return Err("Is a directory");
+        }
+        if offset >= node.size {
+            return Ok(0);
+        }
+        let length = buffer.len().min(node.size - offset);
+        let mut block = inner.nth_block(inode, offset / BLOCK_SIZE);
+        let mut position = offset % BLOCK_SIZE;
+        let mut done = 0;
+        while done < length {
+            let count = (BLOCK_SIZE - position).min(length - done);
+            buffer[done..done + count].copy_from_slice(&inner.data[block][position..position + count]);
+            done += count;
+            position = 0;
+            block = inner.next[block] as usize;
+        }
+        Ok(length)
+    }
+
+    fn write(&self, inode: usize, offset: usize, data: &[u8]) -> Result<usize, &'static str> {
+        let mut inner = self.inner.lock();
+        if inner.node(inode)?.directory {
+            return Err("Is a directory");
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+        if offset + data.len() > inner.nodes[inode].size {
+            inner.resize(inode, offset + data.len())?;
+        }
+        let mut block = inner.nth_block(inode, offset / BLOCK_SIZE);
+        let mut position = offset % BLOCK_SIZE;
+        let mut done = 0;
+        while done < data.len() {
+            let count = (BLOCK_SIZE - position).min(data.len() - done);
+            inner.data[block][position..position + count].copy_from_slice(&data[done..done + count]);
+            done += count;
+            position = 0;
+            block = inner.next[block] as usize;
+        }
+        Ok(data.len())
+    }
+
+    fn truncate(&self, inode: usize, size: usize) -> Result<(), &'static str> {
+        self.inner.lock().resize(inode, size)
+    }
+
+    fn stat(&self, inode: usize) -> Result<Stat, &'static str> {
+        let inner = self.inner.lock();
+        let node = inner.node(inode)?;
+        Ok(Stat { directory: node.directory, size: node.size })
+    }
+
+    fn readdir(&self, directory: usize, f: &mut dyn FnMut(&str, Stat)) -> Result<(), &'static str> {
+        let inner = self.inner.lock();
+        inner.directory(directory)?;
+        for node in inner.nodes[1..].iter().filter(|node| node.used && node.parent == directory) {
+            f(node.name(), Stat { directory: node.directory, size: node.size });
+        }
+        Ok(())
+    }
+
+    fn unlink(&self, directory: usize, name: &str) -> Result<(), &'static str> {
+        let mut inner = self.inner.lock();
+        inner.directory(directory)?;
+        let id = inner.child(directory, name).ok_or("No such file or directory")?;
+        if inner.nodes[id].directory && !inner.is_empty(id) {
+            return Err("Directory not empty");
+        }
+        let first = inner.nodes[id].first;
+        inner.free_chain(first);
+        inner.nodes[id] = EMPTY;
+        Ok(())
+    }
+
+    fn rename(&self, directory: usize, name: &str, target: usize, new_name: &str) -> Result<(), &'static str> {
+        let mut inner = self.inner.lock();
+        inner.directory(directory)?;
+        inner.directory(target)?;
+        if !valid_name(new_name) {
+            return Err("Invalid name");
+        }
+        let id = inner.child(directory, name).ok_or("No such file or directory")?;
+        if inner.within(target, id) {
+            return Err("Cannot move a directory into itself");
+        }
+        if inner.child(target, new_name).is_some() {
+            return Err("File exists");
+        }
+        let node = &mut inner.nodes[id];
+        node.parent = target;
+        node.name = [0; NAME_LENGTH];
+        node.name[..new_name.len()].copy_from_slice(new_name.as_bytes());
+        node.name_length = new_name.len();
+        Ok(())
+    }
+
+    fn usage(&self) -> (usize, usize) {
+        let inner = self.inner.lock();
+        ((BLOCKS - 1) * BLOCK_SIZE, (BLOCKS - 1 - inner.free_blocks()) * BLOCK_SIZE)
+    }
+
+    fn unmount(&self) {
+        self.inner.lock().mounted = false;
+    }
+}
@@ -0,0 +1,101 @@
+use core::fmt::Write;
+use spin::Mutex;
+use crate::{cp437, serial};
+use crate::vga_buffer::{Screen, ANSI_COLORS, WRITER};
+
+/// The number of screenshots kept at a time.
+pub const SLOTS: usize = 8;
+/// The maximum length of a screenshot name.
+pub const NAME_LENGTH: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Shot {
+    name: [u8; NAME_LENGTH],
+    length: usize,
+    screen: Screen,
+}
+
+impl Shot {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.length]).unwrap_or("")
+    }
+}
+
+const EMPTY: Option<Shot> = None;
+
+static SHOTS: Mutex<[Option<Shot>; SLOTS]> = Mutex::new([EMPTY; SLOTS]);
+
+/// Takes a screenshot of the current console, characters and colors included, and keeps it
+/// under `name`, replacing an earlier one with the same name.
+pub fn capture(name: &str) -> Result<(), &'static str> {
+    let valid = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || byte == b'.';
+    if name.is_empty() || name.len() > NAME_LENGTH || !name.bytes().all(valid) {
+        return Err("Names are 1 to 16 letters, digits, '-', '_' or '.'");
+    }
+    let screen = WRITER.lock().save();
+    let mut shots = SHOTS.lock();
+    let index = match shots.iter().position(|shot| matches!(shot, Some(shot) if shot.name() == name)) {
+        Some(index) => index,
+        None => shots.iter().position(Option::is_none).ok_or("All screenshot slots are in use")?,
+    };
+    let mut shot = Shot { name: [0; NAME_LENGTH], length: name.len(), screen };
+    shot.name[..name.len()].copy_from_slice(name.as_bytes());
+    shots[index] = Some(shot);
+    Ok(())
+}
+
+/// Returns the screenshot with the given name.
+pub fn find(name: &str) -> Option<Screen> {
+    SHOTS.lock().iter().flatten().find(|shot| shot.name() == name).map(|shot| shot.screen)
+}
+
+/// Deletes a screenshot; returns `false` if there is none with that name.
+pub fn delete(name: &str) -> bool {
+    let mut shots = SHOTS.lock();
+    match shots.iter_mut().find(|shot| matches!(shot, Some(shot) if shot.name() == name)) {
+        Some(shot) => {
+            *shot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Calls `f` with the name and text mode size of each screenshot.
+pub fn list<F: FnMut(&str, (usize, usize))>(mut f: F) {
+    for shot in SHOTS.lock().iter().flatten() {
+        f(shot.name(), shot.screen.size());
+    }
+}
+
+/// Sends a screenshot over the serial port, one line per row. Plain text drops the colors and
+/// trailing blanks; with `ansi` every color change is sent as an SGR escape sequence instead.
+pub fn export(name: &str, ansi: bool) -> Result<(), &'static str> {
+    let screen = find(name).ok_or("No such screenshot")?;
+    let (width, height) = screen.size();
+    let mut serial = serial::Writer;
+    for row in 0..height {
+        let end = if ansi {
+            width
+        } else {
+            (0..width).rev().find(|&col| screen.cell(row, col).0 != b' ').map_or(0, |col| col + 1)
+        };
+        let mut color = None;
+        for col in 0..end {
+            let (character, color_code) = screen.cell(row, col);
+            if ansi && color != Some(color_code) {
+                let (foreground, background) = (color_code.foreground() as u8, color_code.background() as u8);
+                let _ = write!(
+                    serial,
+                    "\x1b[{};{}m",
+                    ANSI_COLORS[foreground as usize & 7] + if foreground & 8 != 0 { 90 } else { 30 },
+                    ANSI_COLORS[background as usize & 7] + if background & 8 != 0 { 100 } else { 40 },
+                );
+                color = Some(color_code);
+            }
+            let _ = serial.write_char(cp437::decode(character));
+        }
+        let _ = serial.write_str(if ansi { "\x1b[0m\r\n" } else { "\r\n" });
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use core::fmt;
+use x86_64::instructions::port::Port;
+
+/// The first serial port (COM1).
+const COM1: u16 = 0x3F8;
+
+const LINE_STATUS: u16 = COM1 + 5;
+/// Set in the line status register when the transmitter can take another byte.
+const TRANSMIT_EMPTY: u8 = 0x20;
+
+/// Number of status polls before a byte is dropped, so a missing port cannot hang output.
+const TIMEOUT: usize = 100_000;
+
+fn write_register(offset: u16, value: u8) {
+    let mut port: Port<u8> = Port::new(COM1 + offset);
+    unsafe { port.write(value) }
+}
+
+/// Sets COM1 to 38400 baud, 8 data bits, no parity and one stop bit, with the FIFOs enabled
+/// and interrupts off.
+pub fn init() {
+    write_register(1, 0x00);
+    // divisor latch access, then a divisor of 3 (115200 / 3)
+    write_register(3, 0x80);
+    write_register(0, 0x03);
+    write_register(1, 0x00);
+    write_register(3, 0x03);
+    write_register(2, 0xC7);
+    write_register(4, 0x03);
+}
+
+/// Sends a byte once the transmitter is ready.
+pub fn write_byte(byte: u8) {
+    let mut status: Port<u8> = Port::new(LINE_STATUS);
+    for _ in 0..TIMEOUT {
+        if unsafe { status.read() } & TRANSMIT_EMPTY != 0 {
+            write_register(0, byte);
+            return;
+        }
+    }
+}
+
+/// Formats text onto the serial port as UTF-8.
+pub struct Writer;
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            write_byte(byte);
+        }
+        Ok(())
+    }
+}
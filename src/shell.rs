@@ -1,6 +1,7 @@
 use crate::{print, println};
 use crate::configuration::execute;
 use crate::keyboard_buffer;
+use crate::vga_buffer::{CursorShape, WRITER};
 
 const BUFFER_SIZE: usize = 79;
 
@@ -17,13 +18,25 @@ impl Buffer {
         }
     }
 
-    fn add_char(&mut self, c: u8) {
-        if self.index < BUFFER_SIZE - 1 {
-            self.buffer[self.index] = c;
-            self.index += 1;
+    fn add_char(&mut self, c: char) {
+        let length = c.len_utf8();
+        if self.index + length < BUFFER_SIZE {
+            c.encode_utf8(&mut self.buffer[self.index..]);
+            self.index += length;
         }
     }
 
+    fn remove_char(&mut self) -> bool {
+        if self.index == 0 {
+            return false;
+        }
+        self.index -= 1;
+        while self.index > 0 && self.buffer[self.index] & 0xC0 == 0x80 {
+            self.index -= 1;
+        }
+        true
+    }
+
     fn reset(&mut self) {
         self.index = 0;
         self.buffer = [0; BUFFER_SIZE];
@@ -35,6 +48,11 @@ impl Buffer {
 }
 
 pub fn shell() {
+    {
+        let mut writer = WRITER.lock();
+        writer.set_cursor_shape(CursorShape::Underline);
+        writer.set_cursor_visible(true);
+    }
     println!("Welcome to the h-3x shell");
     println!("Enter 'help' to list all the commands");
     println!("Enter 'manual' to display the system manual");
@@ -42,9 +60,15 @@ pub fn shell() {
     let mut buffer = Buffer::new();
     loop {
         let character = keyboard_buffer::read_char();
+        if character == '\u{8}' {
+            if buffer.remove_char() {
+                print!("{}", character);
+            }
+            continue;
+        }
         if character != '\0' {
             print!("{}", character);
-            buffer.add_char(character as u8);
+            buffer.add_char(character);
             if buffer.index == BUFFER_SIZE - 1 && character != '\n' {
                 let input = buffer.get_input();
                 let input_str = core::str::from_utf8(input).unwrap_or("<invalid UTF-8>");
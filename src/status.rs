@@ -0,0 +1,104 @@
+use core::fmt::{self, Write};
+use crate::commands::{self, Time};
+use crate::vga_buffer::{self, Color, ColorCode};
+use crate::{console, gfx, keyboard_buffer, vbe, ERROR};
+
+/// `tick` calls between reads of the clock.
+const TICKS_PER_CHECK: u32 = 0x400;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+static mut TICKS: u32 = 0;
+/// Seconds since midnight at boot.
+static mut BOOT_SECOND: u64 = 0;
+/// Seconds since midnight when the status row was last drawn.
+static mut LAST_SECOND: u64 = 0;
+/// Midnights passed since boot.
+static mut DAYS: u64 = 0;
+
+/// A line of status text formatted without an allocator.
+struct Line {
+    bytes: [u8; vga_buffer::BUFFER_WIDTH],
+    length: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let length = s.len().min(self.bytes.len() - self.length);
+        self.bytes[self.length..self.length + length].copy_from_slice(&s.as_bytes()[..length]);
+        self.length += length;
+        Ok(())
+    }
+}
+
+fn seconds_of_day(time: &Time) -> u64 {
+    time.hours as u64 * 3600 + time.minutes as u64 * 60 + time.seconds as u64
+}
+
+/// Remembers the boot time that the uptime is counted from.
+pub fn init() {
+    let second = match commands::read_rtc() {
+        Ok(time) => seconds_of_day(&time),
+        Err(_) => return,
+    };
+    unsafe {
+        BOOT_SECOND = second;
+        LAST_SECOND = second;
+    }
+}
+
+/// Called from the input polling loops; redraws the status row about once a second.
+pub fn tick() {
+    if vga_buffer::status_position().is_none() || gfx::active() || vbe::active() {
+        return;
+    }
+    unsafe {
+        TICKS += 1;
+        if TICKS < TICKS_PER_CHECK {
+            return;
+        }
+        TICKS = 0;
+    }
+    if let Ok(time) = commands::read_rtc() {
+        if seconds_of_day(&time) != unsafe { LAST_SECOND } {
+            draw(&time);
+        }
+    }
+}
+
+/// Redraws the status row right away, for changes that should not wait for the clock.
+pub fn refresh() {
+    if vga_buffer::status_position().is_some() && !gfx::active() && !vbe::active() {
+        if let Ok(time) = commands::read_rtc() {
+            draw(&time);
+        }
+    }
+}
+
+fn draw(time: &Time) {
+    let second = seconds_of_day(time);
+    let uptime = unsafe {
+        if second < LAST_SECOND {
+            DAYS += 1;
+        }
+        LAST_SECOND = second;
+        DAYS * SECONDS_PER_DAY + second - BOOT_SECOND
+    };
+
+    let mut line = Line { bytes: [0; vga_buffer::BUFFER_WIDTH], length: 0 };
+    let _ = write!(line, " tty{}  {}  up {}:{:02}:{:02}", console::visible() + 1, time,
+        uptime / 3600, uptime / 60 % 60, uptime % 60);
+    if keyboard_buffer::caps_lock() {
+        let _ = line.write_str("  CAPS");
+    }
+    if keyboard_buffer::num_lock() {
+        let _ = line.write_str("  NUM");
+    }
+    let right = if unsafe { ERROR } { " ERROR " } else { "" };
+    vga_buffer::draw_status(
+        core::str::from_utf8(&line.bytes[..line.length]).unwrap_or(""),
+        right,
+        ColorCode::new(Color::Black, Color::LightGray),
+        ColorCode::new(Color::White, Color::Red),
+    );
+}
@@ -0,0 +1,164 @@
+use crate::ata::{self, DISKS, SECTOR_SIZE};
+use crate::{fs, print, println, VARIABLE};
+
+/// The variable is kept in the sectors after the partition table, which partitioning tools
+/// leave unused before the first partition (up to 1 MiB with the usual alignment). CMOS NVRAM
+/// is not used, as the BIOS keeps its own settings in most of it.
+const FIRST_SECTOR: u64 = 1;
+const SECTORS: usize = 8;
+const SIZE: usize = SECTORS * SECTOR_SIZE;
+
+const MAGIC: [u8; 4] = *b"H3XV";
+const VERSION: u16 = 1;
+/// Magic, version, 2 reserved bytes, length and CRC-32 of the data that follows.
+const HEADER_SIZE: usize = 16;
+const CAPACITY: usize = SIZE - HEADER_SIZE;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Returns the first disk with a partition table and room before its first partition, and
+/// whether that room is either blank or already holds the variable. Disks where it does are
+/// preferred. Boot loaders such as GRUB keep their code in the same place, so a region with
+/// anything else in it, including a saved variable whose header was damaged, is never touched.
+fn find() -> Option<(usize, bool)> {
+    let mut in_use = None;
+    let mut mbr = [0; SECTOR_SIZE];
+    let mut region = [0; SIZE];
+    for index in (0..DISKS).filter(|&index| ata::disk(index).is_some()) {
+        if ata::read(index, 0, &mut mbr).is_err() || mbr[510..] != [0x55, 0xAA] {
+            continue;
+        }
+        // byte 4 of an entry is the partition type, 0 if unused, and bytes 8 to 11 the start
+        let first = mbr[446..510]
+            .chunks_exact(16)
+            .filter(|entry| entry[4] != 0)
+            .map(|entry| u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]))
+            .min();
+        if !matches!(first, Some(first) if first as u64 >= FIRST_SECTOR + SECTORS as u64) {
+            continue;
+        }
+        if ata::read(index, FIRST_SECTOR, &mut region).is_err() {
+            continue;
+        }
+        if region[..4] == MAGIC || region.iter().all(|&byte| byte == 0) {
+            return Some((index, true));
+        }
+        in_use = in_use.or(Some((index, false)));
+    }
+    in_use
+}
+
+/// Returns the disk that the variable is saved to and loaded from.
+fn disk() -> Result<usize, &'static str> {
+    match find() {
+        Some((index, true)) => Ok(index),
+        Some((_, false)) => Err("The sectors before the first partition hold other data and were left untouched"),
+        None => Err("No partitioned disk with room for the variable"),
+    }
+}
+
+/// Explains why nothing was written to the sectors of a disk that hold something else.
+fn untouched(index: usize) {
+    println!(
+        "The sectors before the first partition of {} hold something else, such as a boot loader \
+         or a damaged saved variable, and were left untouched",
+        ata::NAMES[index]
+    );
+}
+
+/// Writes the variable to disk and returns the disk name and the number of bytes saved.
+pub fn save() -> Result<(&'static str, usize), &'static str> {
+    let index = disk()?;
+    let mut image = [0; SIZE];
+    let fd = fs::open(VARIABLE, fs::READ | fs::CREATE)?;
+    let mut length = 0;
+    let result = loop {
+        match fs::read(fd, &mut image[HEADER_SIZE + length..]) {
+            Ok(0) => break Ok(()),
+            Ok(count) => length += count,
+            Err(message) => break Err(message),
+        }
+        if length == CAPACITY {
+            // anything left over does not fit
+            let mut byte = [0];
+            break match fs::read(fd, &mut byte) {
+                Ok(0) => Ok(()),
+                _ => Err("The variable is too large to save"),
+            };
+        }
+    };
+    fs::close(fd);
+    result?;
+    let crc = crc32(&image[HEADER_SIZE..HEADER_SIZE + length]);
+    image[..4].copy_from_slice(&MAGIC);
+    image[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    image[8..12].copy_from_slice(&(length as u32).to_le_bytes());
+    image[12..16].copy_from_slice(&crc.to_le_bytes());
+    ata::write(index, FIRST_SECTOR, &image)?;
+    Ok((ata::NAMES[index], length))
+}
+
+/// Replaces the variable with the saved copy and returns the disk name and the number of bytes
+/// loaded, or `None` if nothing was ever saved.
+pub fn load() -> Result<Option<(&'static str, usize)>, &'static str> {
+    let index = disk()?;
+    let mut image = [0; SIZE];
+    ata::read(index, FIRST_SECTOR, &mut image)?;
+    if image[..4] != MAGIC {
+        return Ok(None);
+    }
+    if u16::from_le_bytes([image[4], image[5]]) != VERSION {
+        return Err("The saved variable has an unknown version");
+    }
+    let length = u32::from_le_bytes([image[8], image[9], image[10], image[11]]) as usize;
+    let crc = u32::from_le_bytes([image[12], image[13], image[14], image[15]]);
+    if length > CAPACITY || crc32(&image[HEADER_SIZE..HEADER_SIZE + length]) != crc {
+        return Err("The saved variable is corrupt");
+    }
+    let fd = fs::open(VARIABLE, fs::WRITE | fs::CREATE | fs::TRUNCATE)?;
+    let result = fs::write(fd, &image[HEADER_SIZE..HEADER_SIZE + length]);
+    fs::close(fd);
+    result?;
+    Ok(Some((ata::NAMES[index], length)))
+}
+
+/// Saves the variable if a disk can hold it, before halting or rebooting.
+pub fn autosave() -> Result<(), &'static str> {
+    match find() {
+        Some((_, true)) => save().map(|_| ()),
+        Some((index, false)) => {
+            untouched(index);
+            println!("The variable was not saved");
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Loads the saved variable at boot, warning if it cannot be trusted.
+pub fn init() {
+    match find() {
+        Some((_, true)) => (),
+        Some((index, false)) => {
+            print!("WARNING: ");
+            untouched(index);
+            println!("Starting with an empty variable");
+            return;
+        }
+        None => return,
+    }
+    match load() {
+        Ok(Some((disk, length))) => println!("Loaded the variable ({} bytes) from {}", length, disk),
+        Ok(None) => (),
+        Err(message) => println!("WARNING: {}, starting with an empty variable", message),
+    }
+}
@@ -0,0 +1,81 @@
+use core::arch::asm;
+use bootloader::bootinfo::MemoryRegionType;
+use x86_64::instructions::segmentation::{Segment, CS, DS, SS};
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::registers::model_specific::Efer;
+use x86_64::registers::rflags;
+use crate::vga_buffer::{self, WRITER};
+use crate::{commands, configuration, console, gfx, fs, keymap, memory, mouse, println, theme, vbe, ERROR};
+
+/// The key of the SysRq reboot action, which runs as soon as it is pressed.
+pub const REBOOT: u8 = 0x30;
+
+/// Runs the Magic SysRq action bound to the key pressed after Alt+SysRq.
+///
+/// Keys are matched by scancode so the bindings stay on the same physical keys whatever
+/// layout is active.
+pub fn handle(scancode: u8) {
+    match scancode {
+        REBOOT => commands::restart(),
+        0x32 => show_memory(),
+        0x19 => dump_registers(),
+        0x14 => show_state(),
+        0x2F => reset_vga(),
+        _ => println!("SysRq: reboot(b) memory(m) registers(p) state(t) vga(v)"),
+    }
+}
+
+fn dump_registers() {
+    let rsp: u64;
+    let rbp: u64;
+    unsafe {
+        asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+        asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+    }
+    let (frame, _) = Cr3::read();
+    println!("SysRq: registers");
+    println!("RSP: {:#018x} RBP: {:#018x} RFLAGS: {:#018x}", rsp, rbp, rflags::read_raw());
+    println!("CR0: {:#018x} CR2: {:#018x}", Cr0::read_raw(), Cr2::read().as_u64());
+    println!("CR3: {:#018x} CR4: {:#018x}", frame.start_address().as_u64(), Cr4::read_raw());
+    println!("EFER: {:#018x} CS: {:#06x} DS: {:#06x} SS: {:#06x}",
+        Efer::read_raw(), CS::get_reg().0, DS::get_reg().0, SS::get_reg().0);
+}
+
+fn show_state() {
+    println!("SysRq: state");
+    println!("Console: {} of {}", console::current() + 1, console::CONSOLES);
+    configuration::with_running(|command| match command {
+        Some(command) => println!("Command: {}", command),
+        None => println!("Command: none (shell prompt)"),
+    });
+    println!("Last command failed: {}", if unsafe { ERROR } { "yes" } else { "no" });
+    println!("Keymap: {}", keymap::active().name);
+    println!("Mouse: {}", if mouse::present() { "present" } else { "absent" });
+}
+
+fn show_memory() {
+    println!("SysRq: memory");
+    println!("Usable: {} KiB", memory::total(MemoryRegionType::Usable) / 1024);
+    println!("Kernel: {} KiB", memory::total(MemoryRegionType::Kernel) / 1024);
+    println!("Kernel stack: {} KiB", memory::total(MemoryRegionType::KernelStack) / 1024);
+    println!("Memory map entries: {}", memory::regions());
+    fs::mounts(|path, _, kind, (size, used)| {
+        println!("Filesystem {} ({}): {} of {} KiB used", path, kind, used / 1024, size / 1024);
+    });
+}
+
+/// Returns to text mode from mode 13h or VBE, forcibly releases `WRITER` if a command left it locked and resets the
+/// screen.
+fn reset_vga() {
+    gfx::leave();
+    vbe::leave();
+    if WRITER.try_lock().is_none() {
+        unsafe {
+            WRITER.force_unlock();
+        }
+    }
+    WRITER.lock().reset();
+    vga_buffer::set_blink(true);
+    theme::apply("default");
+    println!("SysRq: VGA reset");
+}
@@ -0,0 +1,77 @@
+use crate::vga_buffer::{self, Color, WRITER};
+
+/// A named set of DAC colors for the 16 text mode colors plus the default text colors.
+pub struct Theme {
+    pub name: &'static str,
+    foreground: Color,
+    background: Color,
+    /// Red, green and blue intensities (0-63 each), indexed by `Color`.
+    palette: [(u8, u8, u8); 16],
+}
+
+static DEFAULT: Theme = Theme {
+    name: "default",
+    foreground: Color::White,
+    background: Color::Black,
+    palette: [
+        (0, 0, 0), (0, 0, 42), (0, 42, 0), (0, 42, 42),
+        (42, 0, 0), (42, 0, 42), (42, 21, 0), (42, 42, 42),
+        (21, 21, 21), (21, 21, 63), (21, 63, 21), (21, 63, 63),
+        (63, 21, 21), (63, 21, 63), (63, 63, 21), (63, 63, 63),
+    ],
+};
+
+// Solarized dark: the background tones sit in the low slots so they also work as
+// backgrounds while blinking is enabled.
+static SOLARIZED: Theme = Theme {
+    name: "solarized",
+    foreground: Color::LightBlue,
+    background: Color::Black,
+    palette: [
+        (0, 10, 13), (9, 34, 52), (33, 38, 0), (10, 40, 38),
+        (55, 12, 11), (52, 13, 32), (45, 34, 0), (59, 58, 53),
+        (1, 13, 16), (32, 37, 37), (22, 27, 29), (36, 40, 40),
+        (50, 18, 5), (27, 28, 49), (25, 30, 32), (63, 61, 56),
+    ],
+};
+
+static AMBER: Theme = Theme {
+    name: "amber",
+    foreground: Color::LightGray,
+    background: Color::Black,
+    palette: [
+        (0, 0, 0), (32, 22, 0), (32, 22, 0), (32, 22, 0),
+        (32, 22, 0), (32, 22, 0), (32, 22, 0), (48, 33, 0),
+        (20, 14, 0), (56, 39, 0), (56, 39, 0), (56, 39, 0),
+        (56, 39, 0), (56, 39, 0), (56, 39, 0), (63, 44, 0),
+    ],
+};
+
+static GREEN: Theme = Theme {
+    name: "green",
+    foreground: Color::LightGray,
+    background: Color::Black,
+    palette: [
+        (0, 0, 0), (6, 32, 0), (6, 32, 0), (6, 32, 0),
+        (6, 32, 0), (6, 32, 0), (6, 32, 0), (9, 48, 0),
+        (4, 20, 0), (11, 56, 0), (11, 56, 0), (11, 56, 0),
+        (11, 56, 0), (11, 56, 0), (11, 56, 0), (12, 63, 0),
+    ],
+};
+
+/// All available themes, selectable by name with `apply`.
+pub static THEMES: [&Theme; 4] = [&DEFAULT, &SOLARIZED, &AMBER, &GREEN];
+
+/// Programs the palette of the theme with the given name and makes its colors the default
+/// text colors. Returns `false` if there is no such theme.
+pub fn apply(name: &str) -> bool {
+    let theme = match THEMES.iter().find(|theme| theme.name == name) {
+        Some(theme) => theme,
+        None => return false,
+    };
+    for (index, &rgb) in theme.palette.iter().enumerate() {
+        vga_buffer::set_palette(Color::from_index(index as u8), rgb);
+    }
+    WRITER.lock().color(theme.foreground, theme.background);
+    true
+}
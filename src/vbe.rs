@@ -0,0 +1,378 @@
+use core::fmt::{self, Write};
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+use crate::{cp437, memory, pci, status};
+use crate::vga::{self, GLYPH_SIZE};
+use crate::vga_buffer::{self, Ansi, Color, ColorCode, Screen, Sequence, WRITER};
+
+/// The Bochs VBE ("dispi") index and data ports.
+const INDEX_PORT: u16 = 0x1CE;
+const DATA_PORT: u16 = 0x1CF;
+
+const REGISTER_ID: u16 = 0;
+const REGISTER_XRES: u16 = 1;
+const REGISTER_YRES: u16 = 2;
+const REGISTER_BPP: u16 = 3;
+const REGISTER_ENABLE: u16 = 4;
+
+const ENABLED: u16 = 0x01;
+const LFB_ENABLED: u16 = 0x40;
+
+/// Interface versions reported in the ID register by Bochs, QEMU and VirtualBox.
+const IDS: core::ops::RangeInclusive<u16> = 0xB0C0..=0xB0C5;
+
+/// PCI vendor and device IDs of adapters with the dispi interface: QEMU/Bochs `-vga std`
+/// and VirtualBox. The linear framebuffer is behind BAR0 on both.
+const DEVICES: [(u16, u16); 2] = [(0x1234, 0x1111), (0x80EE, 0xBEEF)];
+
+/// Width of a character cell; the height is that of the text mode font.
+const GLYPH_WIDTH: usize = 8;
+const TAB_WIDTH: usize = 8;
+
+/// The mode set with `enter` and the text console drawn on it.
+struct Display {
+    /// Virtual address of the linear framebuffer, 32 bits per pixel.
+    framebuffer: usize,
+    width: usize,
+    height: usize,
+    font_height: usize,
+    row: usize,
+    col: usize,
+    /// The text colors, drawn as `foreground` and `background` pixels.
+    color_code: ColorCode,
+    /// The colors of the text console when the mode was set, restored by SGR 0, 39 and 49.
+    default_color: ColorCode,
+    foreground: u32,
+    background: u32,
+    /// Escape sequences are handled as on the text mode console.
+    ansi: Ansi,
+    /// Cursor row, column and colors saved with `ESC 7` or `CSI s`.
+    saved: (usize, usize, ColorCode),
+}
+
+static DISPLAY: Mutex<Display> = Mutex::new(Display {
+    framebuffer: 0,
+    width: 0,
+    height: 0,
+    font_height: 16,
+    row: 0,
+    col: 0,
+    color_code: ColorCode::new(Color::White, Color::Black),
+    default_color: ColorCode::new(Color::White, Color::Black),
+    foreground: 0xFFFFFF,
+    background: 0,
+    ansi: Ansi::new(),
+    saved: (0, 0, ColorCode::new(Color::White, Color::Black)),
+});
+
+/// The text screen, put back on `leave`.
+static SCREEN: Mutex<Option<Screen>> = Mutex::new(None);
+
+static mut ACTIVE: bool = false;
+
+fn write_register(index: u16, value: u16) {
+    let mut index_port: Port<u16> = Port::new(INDEX_PORT);
+    let mut data_port: Port<u16> = Port::new(DATA_PORT);
+    unsafe {
+        index_port.write(index);
+        data_port.write(value);
+    }
+}
+
+fn read_register(index: u16) -> u16 {
+    let mut index_port: Port<u16> = Port::new(INDEX_PORT);
+    let mut data_port: Port<u16> = Port::new(DATA_PORT);
+    unsafe {
+        index_port.write(index);
+        data_port.read()
+    }
+}
+
+/// Returns `true` if the adapter has the Bochs VBE interface.
+pub fn present() -> bool {
+    IDS.contains(&read_register(REGISTER_ID))
+}
+
+/// Returns `true` while a VBE mode is set.
+pub fn active() -> bool {
+    unsafe { ACTIVE }
+}
+
+/// Converts a text mode color to a 32-bit pixel through the current palette.
+fn pixel_color(color: Color) -> u32 {
+    let (red, green, blue) = vga_buffer::palette(color);
+    let scale = |level: u8| (level as u32 * 255 / 63) & 0xFF;
+    scale(red) << 16 | scale(green) << 8 | scale(blue)
+}
+
+/// Sets a `width` x `height` mode with 32 bits per pixel and shows a text console on it with
+/// the current text colors.
+///
+/// The width must be a multiple of 8 between 320 and 1920 and the height between 200 and
+/// 1200. The text mode state is saved on the first call and put back by `leave`; calling
+/// this again while active only changes the resolution.
+pub fn enter(width: usize, height: usize) -> Result<(), &'static str> {
+    if !(320..=1920).contains(&width) || !width.is_multiple_of(GLYPH_WIDTH) || !(200..=1200).contains(&height) {
+        return Err("Unsupported resolution");
+    }
+    if !present() {
+        return Err("No Bochs VBE interface found");
+    }
+    let location = DEVICES
+        .iter()
+        .find_map(|&(vendor, device)| pci::find(vendor, device))
+        .ok_or("No VBE display adapter on the PCI bus")?;
+    let base = pci::bar_address(location, 0);
+    if base == 0 {
+        return Err("The display adapter has no framebuffer address");
+    }
+    let framebuffer = memory::map_physical(base, (width * height * 4) as u64)
+        .ok_or("Could not map the framebuffer")?;
+
+    if !active() {
+        let mut writer = WRITER.lock();
+        let color_code = writer.color_code();
+        *SCREEN.lock() = Some(writer.save());
+        drop(writer);
+        let mut display = DISPLAY.lock();
+        display.default_color = color_code;
+        display.set_color(color_code);
+        display.ansi = Ansi::new();
+        display.saved = (0, 0, color_code);
+        vga::save_text_mode();
+        display.font_height = vga::with_saved_font(|_, height| height);
+    }
+
+    write_register(REGISTER_ENABLE, 0);
+    write_register(REGISTER_XRES, width as u16);
+    write_register(REGISTER_YRES, height as u16);
+    write_register(REGISTER_BPP, 32);
+    write_register(REGISTER_ENABLE, ENABLED | LFB_ENABLED);
+    unsafe {
+        ACTIVE = true;
+    }
+    if read_register(REGISTER_XRES) as usize != width || read_register(REGISTER_YRES) as usize != height {
+        leave();
+        return Err("The adapter rejected the resolution");
+    }
+
+    let mut display = DISPLAY.lock();
+    display.framebuffer = framebuffer as usize;
+    display.width = width;
+    display.height = height;
+    display.clear();
+    Ok(())
+}
+
+/// Turns the VBE mode off and returns to the saved text mode and screen.
+pub fn leave() {
+    if !active() {
+        return;
+    }
+    write_register(REGISTER_ENABLE, 0);
+    vga::restore_text_mode();
+    unsafe {
+        ACTIVE = false;
+    }
+    if let Some(screen) = SCREEN.lock().take() {
+        WRITER.lock().restore(&screen);
+    }
+    status::refresh();
+}
+
+/// Returns the size of the text console in columns and rows.
+pub fn text_size() -> (usize, usize) {
+    let display = DISPLAY.lock();
+    (display.width / GLYPH_WIDTH, display.height / display.font_height)
+}
+
+/// Blanks the console and moves its cursor to the top left corner.
+pub fn clear() {
+    if active() {
+        DISPLAY.lock().clear();
+    }
+}
+
+impl Display {
+    fn pixels(&self) -> *mut u32 {
+        self.framebuffer as *mut u32
+    }
+
+    fn rows(&self) -> usize {
+        self.height / self.font_height
+    }
+
+    fn columns(&self) -> usize {
+        self.width / GLYPH_WIDTH
+    }
+
+    fn set_color(&mut self, color_code: ColorCode) {
+        self.color_code = color_code;
+        self.foreground = pixel_color(color_code.foreground());
+        self.background = pixel_color(color_code.background());
+    }
+
+    fn fill(&self, y: usize, height: usize, color: u32) {
+        let pixels = self.pixels();
+        for offset in y * self.width..(y + height) * self.width {
+            unsafe { pixels.add(offset).write_volatile(color) };
+        }
+    }
+
+    fn clear(&mut self) {
+        self.fill(0, self.height, self.background);
+        self.row = 0;
+        self.col = 0;
+    }
+
+    /// Draws a character cell at the cursor.
+    fn put(&self, character: char) {
+        let glyph = cp437::encode(character) as usize * GLYPH_SIZE;
+        let pixels = self.pixels();
+        let (x, y) = (self.col * GLYPH_WIDTH, self.row * self.font_height);
+        vga::with_saved_font(|font, height| {
+            for row in 0..height.min(self.font_height) {
+                let bits = font[glyph + row];
+                let line = (y + row) * self.width + x;
+                for col in 0..GLYPH_WIDTH {
+                    let color = if bits & (0x80 >> col) != 0 { self.foreground } else { self.background };
+                    unsafe { pixels.add(line + col).write_volatile(color) };
+                }
+            }
+        });
+    }
+
+    /// Blanks the cells of a row from `start` up to but not including `end`.
+    fn clear_cells(&mut self, row: usize, start: usize, end: usize) {
+        let pixels = self.pixels();
+        for y in row * self.font_height..(row + 1) * self.font_height {
+            for x in start * GLYPH_WIDTH..end.min(self.columns()) * GLYPH_WIDTH {
+                unsafe { pixels.add(y * self.width + x).write_volatile(self.background) };
+            }
+        }
+    }
+
+    /// Executes a control sequence ending in `command`. Cursor movement, erasing, colors and
+    /// saving the cursor work as on the text mode console; the others are ignored.
+    fn control_sequence(&mut self, command: u8) {
+        if self.ansi.private() {
+            return;
+        }
+        let (rows, columns) = (self.rows(), self.columns());
+        let count = self.ansi.parameter(0, 1);
+        let col = self.col.min(columns - 1);
+        match command {
+            b'A' => self.row = self.row.saturating_sub(count),
+            b'B' => self.row = (self.row + count).min(rows - 1),
+            b'C' => self.col = (col + count).min(columns - 1),
+            b'D' => self.col = col.saturating_sub(count),
+            b'H' | b'f' => {
+                self.row = (self.ansi.parameter(0, 1) - 1).min(rows - 1);
+                self.col = (self.ansi.parameter(1, 1) - 1).min(columns - 1);
+            }
+            // ED: 0 erases to the end of the screen, 1 to its start and 2 (or 3) all of it
+            b'J' => match self.ansi.parameter(0, 0) {
+                0 => {
+                    self.clear_cells(self.row, col, columns);
+                    let below = (self.row + 1) * self.font_height;
+                    self.fill(below, rows * self.font_height - below, self.background);
+                }
+                1 => {
+                    self.fill(0, self.row * self.font_height, self.background);
+                    self.clear_cells(self.row, 0, col + 1);
+                }
+                2 | 3 => self.fill(0, rows * self.font_height, self.background),
+                _ => (),
+            },
+            // EL: 0 erases to the end of the line, 1 to its start and 2 the whole line
+            b'K' => match self.ansi.parameter(0, 0) {
+                0 => self.clear_cells(self.row, col, columns),
+                1 => self.clear_cells(self.row, 0, col + 1),
+                2 => self.clear_cells(self.row, 0, columns),
+                _ => (),
+            },
+            b'm' => {
+                let color_code = self.ansi.graphic_rendition(self.color_code, self.default_color);
+                self.set_color(color_code);
+            }
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            _ => (),
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = (self.row, self.col, self.color_code);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (row, col, color_code) = self.saved;
+        self.row = row.min(self.rows() - 1);
+        self.col = col;
+        self.set_color(color_code);
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows() {
+            self.row += 1;
+            return;
+        }
+        let line = self.font_height * self.width;
+        let last = (self.rows() - 1) * self.font_height;
+        unsafe {
+            core::ptr::copy(self.pixels().add(line), self.pixels(), last * self.width);
+        }
+        self.fill(last, self.font_height, self.background);
+    }
+
+    fn write_character(&mut self, character: char) {
+        let columns = self.columns();
+        match character {
+            '\n' => self.new_line(),
+            '\r' => self.col = 0,
+            '\t' => self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(columns - 1),
+            '\x08' => {
+                if self.col > 0 {
+                    self.col = self.col.min(columns) - 1;
+                    self.put(' ');
+                }
+            }
+            character => {
+                if self.col >= columns {
+                    self.new_line();
+                }
+                self.put(character);
+                self.col += 1;
+            }
+        }
+    }
+}
+
+impl Write for Display {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for character in s.chars() {
+            if self.ansi.pending() {
+                let mut utf8 = [0; 4];
+                for &byte in character.encode_utf8(&mut utf8).as_bytes() {
+                    match self.ansi.parse(byte) {
+                        Some(Sequence::SaveCursor) => self.save_cursor(),
+                        Some(Sequence::RestoreCursor) => self.restore_cursor(),
+                        Some(Sequence::Control(command)) => self.control_sequence(command),
+                        None => (),
+                    }
+                }
+            } else if character == '\x1b' {
+                self.ansi.escape();
+            } else {
+                self.write_character(character);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints to the VBE text console; `print!` ends up here while a VBE mode is set.
+pub fn print(args: fmt::Arguments) {
+    DISPLAY.lock().write_fmt(args).unwrap();
+}
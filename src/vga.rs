@@ -0,0 +1,368 @@
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// The miscellaneous output register's write and read ports.
+const MISC_WRITE: u16 = 0x3C2;
+const MISC_READ: u16 = 0x3CC;
+/// The sequencer's index and data ports.
+const SEQUENCER_INDEX: u16 = 0x3C4;
+const SEQUENCER_DATA: u16 = 0x3C5;
+/// The CRT controller's index and data ports (color text mode addresses).
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+/// The graphics controller's index and data ports.
+const GRAPHICS_INDEX: u16 = 0x3CE;
+const GRAPHICS_DATA: u16 = 0x3CF;
+/// The attribute controller's combined index/data write port and its data read port.
+const ATTRIBUTE_INDEX: u16 = 0x3C0;
+const ATTRIBUTE_READ: u16 = 0x3C1;
+/// Reading the input status register resets the attribute controller's index/data flip-flop.
+const INPUT_STATUS: u16 = 0x3DA;
+/// The DAC's write index, read index and data ports.
+const DAC_WRITE_INDEX: u16 = 0x3C8;
+const DAC_READ_INDEX: u16 = 0x3C7;
+const DAC_DATA: u16 = 0x3C9;
+
+/// Bytes per character in font plane 2; only the first `height` rows of each are used.
+pub const GLYPH_SIZE: usize = 32;
+/// Size of a 256 character font as stored in plane 2.
+pub const FONT_SIZE: usize = 256 * GLYPH_SIZE;
+
+fn write_indexed(index_port: u16, data_port: u16, index: u8, value: u8) {
+    let mut index_port: Port<u8> = Port::new(index_port);
+    let mut data_port: Port<u8> = Port::new(data_port);
+    unsafe {
+        index_port.write(index);
+        data_port.write(value);
+    }
+}
+
+fn read_indexed(index_port: u16, data_port: u16, index: u8) -> u8 {
+    let mut index_port: Port<u8> = Port::new(index_port);
+    let mut data_port: Port<u8> = Port::new(data_port);
+    unsafe {
+        index_port.write(index);
+        data_port.read()
+    }
+}
+
+/// Writes `value` to the CRT controller register with the given index.
+pub fn write_crtc(index: u8, value: u8) {
+    write_indexed(CRTC_INDEX, CRTC_DATA, index, value);
+}
+
+/// Reads the CRT controller register with the given index.
+pub fn read_crtc(index: u8) -> u8 {
+    read_indexed(CRTC_INDEX, CRTC_DATA, index)
+}
+
+pub fn write_sequencer(index: u8, value: u8) {
+    write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, index, value);
+}
+
+pub fn read_sequencer(index: u8) -> u8 {
+    read_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, index)
+}
+
+pub fn write_graphics(index: u8, value: u8) {
+    write_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, index, value);
+}
+
+pub fn read_graphics(index: u8) -> u8 {
+    read_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, index)
+}
+
+/// Reads the attribute controller register with the given index.
+pub fn read_attribute(index: u8) -> u8 {
+    let mut status_port: Port<u8> = Port::new(INPUT_STATUS);
+    let mut index_port: Port<u8> = Port::new(ATTRIBUTE_INDEX);
+    let mut data_port: Port<u8> = Port::new(ATTRIBUTE_READ);
+    unsafe {
+        status_port.read();
+        // Bit 5 keeps the display enabled while the register is accessed.
+        index_port.write(index | 0x20);
+        data_port.read()
+    }
+}
+
+/// Writes `value` to the attribute controller register with the given index.
+pub fn write_attribute(index: u8, value: u8) {
+    let mut status_port: Port<u8> = Port::new(INPUT_STATUS);
+    let mut port: Port<u8> = Port::new(ATTRIBUTE_INDEX);
+    unsafe {
+        status_port.read();
+        port.write(index | 0x20);
+        port.write(value);
+    }
+}
+
+/// Sets the red, green and blue intensities (0-63 each) of a DAC entry.
+pub fn write_dac(entry: u8, rgb: (u8, u8, u8)) {
+    let mut index_port: Port<u8> = Port::new(DAC_WRITE_INDEX);
+    let mut data_port: Port<u8> = Port::new(DAC_DATA);
+    unsafe {
+        index_port.write(entry);
+        data_port.write(rgb.0 & 0x3F);
+        data_port.write(rgb.1 & 0x3F);
+        data_port.write(rgb.2 & 0x3F);
+    }
+}
+
+/// Returns the red, green and blue intensities (0-63 each) of a DAC entry.
+pub fn read_dac(entry: u8) -> (u8, u8, u8) {
+    let mut index_port: Port<u8> = Port::new(DAC_READ_INDEX);
+    let mut data_port: Port<u8> = Port::new(DAC_DATA);
+    unsafe {
+        index_port.write(entry);
+        (data_port.read(), data_port.read(), data_port.read())
+    }
+}
+
+/// The complete set of VGA registers that make up a video mode.
+#[derive(Clone, Copy)]
+pub struct Registers {
+    pub misc: u8,
+    pub sequencer: [u8; 5],
+    pub crtc: [u8; 25],
+    pub graphics: [u8; 9],
+    pub attribute: [u8; 21],
+}
+
+/// 320x200 with 256 colors, one byte per pixel at 0xA0000 (BIOS mode 13h).
+pub const MODE_13H: Registers = Registers {
+    misc: 0x63,
+    sequencer: [0x03, 0x01, 0x0F, 0x00, 0x0E],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0xBF, 0x1F, 0x00, 0x41, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x9C, 0x0E, 0x8F, 0x28, 0x40, 0x96, 0xB9, 0xA3, 0xFF,
+    ],
+    graphics: [0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0F, 0xFF],
+    attribute: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C,
+        0x0D, 0x0E, 0x0F, 0x41, 0x00, 0x0F, 0x00, 0x00,
+    ],
+};
+
+/// The registers that set the timing and character cell of a text mode: the miscellaneous
+/// output register, the sequencer and the CRT controller.
+pub struct Timing {
+    pub misc: u8,
+    pub sequencer: [u8; 5],
+    pub crtc: [u8; 25],
+}
+
+// The text modes below all use 45 for the CRTC offset register (0x13), so rows of the text
+// buffer are 90 characters apart whatever the number of columns shown.
+
+/// 80x25 with the 9x16 character cell of BIOS mode 3, 720x400.
+pub const TEXT_80X25: Timing = Timing {
+    misc: 0x67,
+    sequencer: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x4F, 0x0D, 0x0E, 0x00,
+        0x00, 0x00, 0x00, 0x9C, 0x0E, 0x8F, 0x2D, 0x1F, 0x96, 0xB9, 0xA3, 0xFF,
+    ],
+};
+
+/// 80x50 with a 9x8 character cell, 720x400.
+pub const TEXT_80X50: Timing = Timing {
+    misc: 0x67,
+    sequencer: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x47, 0x06, 0x07, 0x00,
+        0x00, 0x00, 0x00, 0x9C, 0x0E, 0x8F, 0x2D, 0x1F, 0x96, 0xB9, 0xA3, 0xFF,
+    ],
+};
+
+/// 90x30 with an 8x16 character cell, 720x480.
+pub const TEXT_90X30: Timing = Timing {
+    misc: 0xE7,
+    sequencer: [0x03, 0x01, 0x03, 0x00, 0x02],
+    crtc: [
+        0x6B, 0x59, 0x5A, 0x82, 0x60, 0x8D, 0x0B, 0x3E, 0x00, 0x4F, 0x0D, 0x0E, 0x00,
+        0x00, 0x00, 0x00, 0xEA, 0x0C, 0xDF, 0x2D, 0x10, 0xE8, 0x05, 0xA3, 0xFF,
+    ],
+};
+
+/// 90x60 with an 8x8 character cell, 720x480.
+pub const TEXT_90X60: Timing = Timing {
+    misc: 0xE7,
+    sequencer: [0x03, 0x01, 0x03, 0x00, 0x02],
+    crtc: [
+        0x6B, 0x59, 0x5A, 0x82, 0x60, 0x8D, 0x0B, 0x3E, 0x00, 0x47, 0x06, 0x07, 0x00,
+        0x00, 0x00, 0x00, 0xEA, 0x0C, 0xDF, 0x2D, 0x08, 0xE8, 0x05, 0xA3, 0xFF,
+    ],
+};
+
+/// Programs the miscellaneous output register, the sequencer and the CRT controller.
+fn write_timing(misc: u8, sequencer: &[u8; 5], crtc: &[u8; 25]) {
+    unsafe {
+        Port::<u8>::new(MISC_WRITE).write(misc);
+    }
+    for (index, &value) in sequencer.iter().enumerate() {
+        write_sequencer(index as u8, value);
+    }
+
+    // Registers 0-7 are write protected by bit 7 of register 0x11.
+    write_crtc(0x03, read_crtc(0x03) | 0x80);
+    write_crtc(0x11, read_crtc(0x11) & !0x80);
+    for (index, &value) in crtc.iter().enumerate() {
+        let value = match index {
+            0x03 => value | 0x80,
+            0x11 => value & !0x80,
+            _ => value,
+        };
+        write_crtc(index as u8, value);
+    }
+}
+
+impl Timing {
+    /// Switches to this text mode, leaving the graphics controller, the attribute controller's
+    /// palette and the fonts alone.
+    ///
+    /// The horizontal panning is set to match the character width: 9-dot characters need a
+    /// shift of 8 to line up, 8-dot characters none.
+    pub fn write(&self) {
+        write_timing(self.misc, &self.sequencer, &self.crtc);
+        write_attribute(0x13, if self.sequencer[1] & 0x01 == 0 { 0x08 } else { 0x00 });
+    }
+}
+
+impl Registers {
+    /// Reads the registers of the current mode.
+    pub fn read() -> Registers {
+        let mut registers = Registers {
+            misc: unsafe { Port::<u8>::new(MISC_READ).read() },
+            sequencer: [0; 5],
+            crtc: [0; 25],
+            graphics: [0; 9],
+            attribute: [0; 21],
+        };
+        for (index, value) in registers.sequencer.iter_mut().enumerate() {
+            *value = read_sequencer(index as u8);
+        }
+        for (index, value) in registers.crtc.iter_mut().enumerate() {
+            *value = read_crtc(index as u8);
+        }
+        for (index, value) in registers.graphics.iter_mut().enumerate() {
+            *value = read_graphics(index as u8);
+        }
+        for (index, value) in registers.attribute.iter_mut().enumerate() {
+            *value = read_attribute(index as u8);
+        }
+        registers
+    }
+
+    /// Programs all registers, switching the adapter to this mode.
+    ///
+    /// The display is blanked while the attribute controller's palette is loaded, since the
+    /// palette registers only accept writes then.
+    pub fn write(&self) {
+        write_timing(self.misc, &self.sequencer, &self.crtc);
+
+        for (index, &value) in self.graphics.iter().enumerate() {
+            write_graphics(index as u8, value);
+        }
+
+        let mut status_port: Port<u8> = Port::new(INPUT_STATUS);
+        let mut port: Port<u8> = Port::new(ATTRIBUTE_INDEX);
+        unsafe {
+            for (index, &value) in self.attribute.iter().enumerate() {
+                status_port.read();
+                port.write(index as u8);
+                port.write(value);
+            }
+            status_port.read();
+            port.write(0x20);
+        }
+    }
+}
+
+/// Runs `f` with plane 2, where the text mode font lives, mapped linearly at 0xA0000, then
+/// restores the text mode memory layout.
+fn with_font_plane<F: FnOnce(*mut u8)>(f: F) {
+    let map_mask = read_sequencer(0x02);
+    let memory_mode = read_sequencer(0x04);
+    let read_map = read_graphics(0x04);
+    let mode = read_graphics(0x05);
+    let misc = read_graphics(0x06);
+
+    write_sequencer(0x02, 0x04);
+    write_sequencer(0x04, 0x06);
+    write_graphics(0x04, 0x02);
+    write_graphics(0x05, 0x00);
+    write_graphics(0x06, 0x04);
+
+    f(0xA0000 as *mut u8);
+
+    write_sequencer(0x02, map_mask);
+    write_sequencer(0x04, memory_mode);
+    write_graphics(0x04, read_map);
+    write_graphics(0x05, mode);
+    write_graphics(0x06, misc);
+}
+
+/// Copies the font from plane 2 into `font`. Only valid while a text mode is set.
+pub fn read_font(font: &mut [u8; FONT_SIZE]) {
+    with_font_plane(|plane| {
+        for (offset, byte) in font.iter_mut().enumerate() {
+            *byte = unsafe { plane.add(offset).read_volatile() };
+        }
+    });
+}
+
+/// Loads `font` into plane 2. Only valid while a text mode is set.
+pub fn write_font(font: &[u8; FONT_SIZE]) {
+    with_font_plane(|plane| {
+        for (offset, &byte) in font.iter().enumerate() {
+            unsafe { plane.add(offset).write_volatile(byte) };
+        }
+    });
+}
+
+/// Text mode state that graphics modes overwrite, kept by `save_text_mode`.
+struct SavedText {
+    registers: Option<Registers>,
+    dac: [(u8, u8, u8); 256],
+    font: [u8; FONT_SIZE],
+    font_height: usize,
+}
+
+static SAVED_TEXT: Mutex<SavedText> = Mutex::new(SavedText {
+    registers: None,
+    dac: [(0, 0, 0); 256],
+    font: [0; FONT_SIZE],
+    font_height: 16,
+});
+
+/// Saves the registers, DAC and font of the current text mode before a graphics mode
+/// reuses video memory.
+pub fn save_text_mode() {
+    let mut saved = SAVED_TEXT.lock();
+    let registers = Registers::read();
+    saved.font_height = (registers.crtc[0x09] & 0x1F) as usize + 1;
+    saved.registers = Some(registers);
+    for (index, rgb) in saved.dac.iter_mut().enumerate() {
+        *rgb = read_dac(index as u8);
+    }
+    read_font(&mut saved.font);
+}
+
+/// Switches back to the text mode saved with `save_text_mode`, with its font and DAC.
+pub fn restore_text_mode() {
+    let mut saved = SAVED_TEXT.lock();
+    if let Some(registers) = saved.registers.take() {
+        registers.write();
+    }
+    write_font(&saved.font);
+    for (index, &rgb) in saved.dac.iter().enumerate() {
+        write_dac(index as u8, rgb);
+    }
+}
+
+/// Calls `f` with the font saved by `save_text_mode` and its glyph height, for drawing text
+/// in graphics modes.
+pub fn with_saved_font<R, F: FnOnce(&[u8; FONT_SIZE], usize) -> R>(f: F) -> R {
+    let saved = SAVED_TEXT.lock();
+    f(&saved.font, saved.font_height)
+}
@@ -22,18 +22,46 @@
 
 use core::fmt;
 use lazy_static::lazy_static;
-use spin::Mutex;
-use volatile::Volatile;
+use spin::{Mutex, MutexGuard};
+use crate::console::{self, CONSOLES};
+use crate::cp437;
+use crate::gfx;
+use crate::vbe;
+use crate::vga::{self, read_attribute, read_crtc, write_attribute, write_crtc, FONT_SIZE, GLYPH_SIZE};
 
 lazy_static! {
-    /// A global `Writer` instance that can be used for printing to the VGA text buffer.
+    /// One `Writer` per virtual console. The visible console's writer draws into the VGA text
+    /// buffer, the others into a buffer in memory until they are switched to.
+    static ref WRITERS: [Mutex<Writer>; CONSOLES] =
+        core::array::from_fn(|console| Mutex::new(Writer::new(console)));
+}
+
+/// A global handle to the `Writer` of the console the running task belongs to.
+///
+/// Used by the `print!` and `println!` macros.
+pub static WRITER: ConsoleWriter = ConsoleWriter;
+
+/// Hands out the `Writer` of the running task's console; see `WRITER`.
+pub struct ConsoleWriter;
+
+impl ConsoleWriter {
+    pub fn lock(&self) -> MutexGuard<'static, Writer> {
+        WRITERS[console::current()].lock()
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'static, Writer>> {
+        WRITERS[console::current()].try_lock()
+    }
+
+    /// Releases the current console's writer even if a lock on it is still held.
     ///
-    /// Used by the `print!` and `println!` macros.
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::White, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
-    });
+    /// # Safety
+    ///
+    /// Only for recovering from a command that left the writer locked; any guard still in use
+    /// will alias the writer.
+    pub unsafe fn force_unlock(&self) {
+        WRITERS[console::current()].force_unlock()
+    }
 }
 
 /// The standard color palette in VGA text mode.
@@ -59,16 +87,311 @@ pub enum Color {
     White = 15,
 }
 
+impl Color {
+    /// Returns the color with the given palette index (only the low four bits are used).
+    pub fn from_index(index: u8) -> Color {
+        match index & 0x0F {
+            0 => Color::Black,
+            1 => Color::Blue,
+            2 => Color::Green,
+            3 => Color::Cyan,
+            4 => Color::Red,
+            5 => Color::Magenta,
+            6 => Color::Brown,
+            7 => Color::LightGray,
+            8 => Color::DarkGray,
+            9 => Color::LightBlue,
+            10 => Color::LightGreen,
+            11 => Color::LightCyan,
+            12 => Color::LightRed,
+            13 => Color::Pink,
+            14 => Color::Yellow,
+            _ => Color::White,
+        }
+    }
+}
+
+/// The shape of the blinking hardware text cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    /// The bottom two scan lines of the character cell.
+    Underline,
+    /// The whole character cell.
+    Block,
+}
+
+/// Chooses between blinking text (`true`) and bright background colors (`false`) for bit 7
+/// of each attribute, through the attribute mode control register.
+pub fn set_blink(enabled: bool) {
+    let mode = read_attribute(0x10);
+    write_attribute(0x10, if enabled { mode | 0x08 } else { mode & !0x08 });
+}
+
+/// Returns `true` if bit 7 of an attribute makes text blink rather than brighten the
+/// background.
+pub fn blink() -> bool {
+    read_attribute(0x10) & 0x08 != 0
+}
+
+/// Sets the red, green and blue intensities (0-63 each) the given color is displayed with.
+///
+/// Text mode colors go through the attribute controller's palette registers before reaching
+/// the DAC, so the DAC entry is looked up there rather than assumed to equal the color index.
+pub fn set_palette(color: Color, rgb: (u8, u8, u8)) {
+    vga::write_dac(read_attribute(color as u8), rgb);
+}
+
+/// Returns the red, green and blue intensities (0-63 each) the given color is displayed with.
+pub fn palette(color: Color) -> (u8, u8, u8) {
+    vga::read_dac(read_attribute(color as u8))
+}
+
+/// The built-in fonts, derived from the adapter's own font as it was at boot.
+pub const FONTS: [&str; 4] = ["default", "thin", "bold", "dyslexic"];
+
+/// Lowercase letters of the "dyslexic" font, 16 rows each. Strokes are doubled towards the
+/// baseline so letters are heavier at the bottom, and b, d, p and q have their own shapes
+/// instead of being mirror images of each other.
+const DYSLEXIC_LETTERS: [[u8; 16]; 26] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x06, 0x3E, 0x66, 0x66, 0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00], // a
+    [0x00, 0x00, 0xE0, 0x60, 0x60, 0x6C, 0x76, 0x62, 0x62, 0x66, 0x66, 0x7C, 0x00, 0x00, 0x00, 0x00], // b: flag at the top of the stem
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x64, 0x40, 0x40, 0x60, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00], // c
+    [0x00, 0x00, 0x06, 0x06, 0x06, 0x36, 0x4E, 0x46, 0x46, 0x66, 0x66, 0x3F, 0x00, 0x00, 0x00, 0x00], // d: tail at the foot of the stem
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x46, 0x7E, 0x40, 0x60, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00], // e
+    [0x00, 0x00, 0x1C, 0x32, 0x30, 0x7C, 0x30, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00, 0x00, 0x00, 0x00], // f
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3A, 0x4E, 0x46, 0x46, 0x66, 0x66, 0x3E, 0x06, 0x66, 0x3C, 0x00], // g
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x6C, 0x76, 0x62, 0x62, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // h
+    [0x00, 0x00, 0x00, 0x18, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00], // i
+    [0x00, 0x00, 0x00, 0x06, 0x00, 0x0E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06, 0x66, 0x66, 0x3C, 0x00], // j
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x64, 0x68, 0x70, 0x68, 0x66, 0x66, 0x63, 0x00, 0x00, 0x00, 0x00], // k
+    [0x00, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00, 0x00, 0x00, 0x00], // l
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x6A, 0x55, 0x49, 0x49, 0x6D, 0x6D, 0x6D, 0x00, 0x00, 0x00, 0x00], // m
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x6C, 0x76, 0x62, 0x62, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // n
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x46, 0x42, 0x42, 0x66, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00], // o
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x6C, 0x76, 0x62, 0x62, 0x66, 0x76, 0x6C, 0x60, 0x60, 0x60, 0x00], // p: plain descender
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x36, 0x4E, 0x46, 0x46, 0x66, 0x6E, 0x36, 0x06, 0x06, 0x07, 0x00], // q: descender hooked to the right
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x6C, 0x76, 0x60, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x00], // r
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x42, 0x40, 0x3C, 0x02, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00], // s
+    [0x00, 0x00, 0x00, 0x30, 0x30, 0x7C, 0x30, 0x30, 0x30, 0x30, 0x32, 0x1C, 0x00, 0x00, 0x00, 0x00], // t
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x44, 0x44, 0x44, 0x44, 0x66, 0x66, 0x3B, 0x00, 0x00, 0x00, 0x00], // u
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0x42, 0x42, 0x24, 0x36, 0x1C, 0x18, 0x00, 0x00, 0x00, 0x00], // v
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x41, 0x41, 0x49, 0x49, 0x6D, 0x7E, 0x36, 0x00, 0x00, 0x00, 0x00], // w
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0x24, 0x18, 0x18, 0x3C, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // x
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0x42, 0x42, 0x42, 0x66, 0x66, 0x3E, 0x06, 0x66, 0x3C, 0x00], // y
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x04, 0x08, 0x10, 0x30, 0x60, 0x7E, 0x00, 0x00, 0x00, 0x00], // z
+];
+
+/// The adapter's font saved by `init`, and the glyphs of the font currently loaded, kept so
+/// it can be scaled again when a text mode with another cell height is set.
+struct Fonts {
+    original: [u8; FONT_SIZE],
+    height: usize,
+    glyphs: [u8; FONT_SIZE],
+    glyph_height: usize,
+    /// Applied to each row of `glyphs` with the row number and the cell height.
+    weight: fn(u8, usize, usize) -> u8,
+    current: &'static str,
+}
+
+fn regular(bits: u8, _: usize, _: usize) -> u8 {
+    bits
+}
+
+static FONT: Mutex<Fonts> = Mutex::new(Fonts {
+    original: [0; FONT_SIZE],
+    height: 16,
+    glyphs: [0; FONT_SIZE],
+    glyph_height: 16,
+    weight: regular,
+    current: "default",
+});
+
+/// Lays out the text the bootloader left on the screen with rows `BUFFER_WIDTH` characters
+/// apart, like all text modes set by `set_mode`, and saves the font the BIOS loaded into
+/// plane 2, which the built-in fonts are derived from.
+pub fn init() {
+    let mut chars = [BLANK; 80 * 25];
+    for (index, character) in chars.iter_mut().enumerate() {
+        *character = unsafe { VGA_TEXT.add(index).read_volatile() };
+    }
+    write_crtc(0x13, (BUFFER_WIDTH / 2) as u8);
+    let shadow = shadow(0);
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            let character = if row < 25 && col < 80 { chars[row * 80 + col] } else { BLANK };
+            shadow.chars[row][col] = character;
+            unsafe { VGA_TEXT.add(row * BUFFER_WIDTH + col).write_volatile(character) };
+        }
+    }
+
+    let mut fonts = FONT.lock();
+    vga::read_font(&mut fonts.original);
+    fonts.height = cell_height();
+    fonts.glyphs = fonts.original;
+    fonts.glyph_height = fonts.height;
+}
+
+/// Returns the height of a character cell in scan lines.
+pub fn cell_height() -> usize {
+    (read_crtc(0x09) & 0x1F) as usize + 1
+}
+
+/// Returns the name of the loaded font: one of `FONTS`, or "psf" for a loaded font file.
+pub fn font() -> &'static str {
+    FONT.lock().current
+}
+
+/// Loads the current font into plane 2, scaled to the cell height of the current mode. When
+/// shrinking, the glyph rows that fall into one cell row are combined so thin strokes stay.
+fn upload(fonts: &Fonts) {
+    let cell = cell_height();
+    let height = fonts.glyph_height;
+    let mut font = [0u8; FONT_SIZE];
+    for (character, glyph) in fonts.glyphs.chunks_exact(GLYPH_SIZE).enumerate() {
+        for row in 0..cell {
+            let first = row * height / cell;
+            let last = ((row + 1) * height / cell).max(first + 1);
+            let bits = glyph[first..last].iter().fold(0, |bits, &row| bits | row);
+            font[character * GLYPH_SIZE + row] = (fonts.weight)(bits, row, cell);
+        }
+    }
+    vga::write_font(&font);
+}
+
+fn text_mode_shown() -> Result<(), &'static str> {
+    if gfx::active() || vbe::active() {
+        return Err("Only possible in text mode");
+    }
+    Ok(())
+}
+
+/// Loads one of the built-in `FONTS`.
+///
+/// "thin" keeps only the leftmost pixel of each horizontal run and "bold" widens each run by
+/// a pixel. "dyslexic" draws lowercase letters from `DYSLEXIC_LETTERS` and widens the lower
+/// half of every other glyph, so all characters are heavier at the bottom.
+pub fn set_font(name: &str) -> Result<(), &'static str> {
+    text_mode_shown()?;
+    let index = FONTS.iter().position(|&font| font == name).ok_or("Invalid font")?;
+    let mut fonts = FONT.lock();
+    fonts.weight = match index {
+        1 => |bits, _, _| bits & !(bits >> 1),
+        2 => |bits, _, _| bits | bits >> 1,
+        _ => regular,
+    };
+    fonts.glyphs = fonts.original;
+    fonts.glyph_height = fonts.height;
+    if index == 3 {
+        let height = fonts.height;
+        for glyph in fonts.glyphs.chunks_exact_mut(GLYPH_SIZE) {
+            for bits in glyph[height / 2..height].iter_mut() {
+                *bits |= *bits >> 1;
+            }
+        }
+        for (letter, rows) in DYSLEXIC_LETTERS.iter().enumerate() {
+            let glyph = (b'a' as usize + letter) * GLYPH_SIZE;
+            for row in 0..height {
+                fonts.glyphs[glyph + row] = rows[row * rows.len() / height];
+            }
+        }
+    }
+    fonts.current = FONTS[index];
+    upload(&fonts);
+    Ok(())
+}
+
+/// The largest part of a PSF file `load_psf` uses: a PSF2 header and 256 glyphs of 32 rows.
+pub const PSF_SIZE: usize = 32 + 256 * GLYPH_SIZE;
+
+/// Loads a PC Screen Font (PSF1 or PSF2) file. Glyphs must be 8 pixels wide and at most 32
+/// rows high; the first 256 are used in code page 437 order.
+pub fn load_psf(data: &[u8]) -> Result<(), &'static str> {
+    text_mode_shown()?;
+    let (offset, count, stride, height) = if data.len() >= 4 && data[..2] == [0x36, 0x04] {
+        // PSF1: magic, mode (bit 0: 512 glyphs), bytes per glyph
+        let count = if data[2] & 0x01 != 0 { 512 } else { 256 };
+        (4, count, data[3] as usize, data[3] as usize)
+    } else if data.len() >= 32 && data[..4] == [0x72, 0xB5, 0x4A, 0x86] {
+        // PSF2: magic, version, header size, flags, glyph count, bytes per glyph, height, width
+        let field = |index: usize| {
+            let bytes = [data[index * 4], data[index * 4 + 1], data[index * 4 + 2], data[index * 4 + 3]];
+            u32::from_le_bytes(bytes) as usize
+        };
+        if field(7) > 8 {
+            return Err("Only fonts 8 pixels wide can be loaded");
+        }
+        (field(2), field(4), field(5), field(6))
+    } else {
+        return Err("Not a PSF1 or PSF2 font");
+    };
+    if height == 0 || height > GLYPH_SIZE || stride < height {
+        return Err("Unsupported glyph size");
+    }
+    let end = offset.saturating_add(count.min(256).saturating_mul(stride));
+    if end > data.len() {
+        return Err("The font file is truncated");
+    }
+    let mut fonts = FONT.lock();
+    fonts.glyphs = [0; FONT_SIZE];
+    for (character, glyph) in data[offset..end].chunks_exact(stride).enumerate() {
+        fonts.glyphs[character * GLYPH_SIZE..character * GLYPH_SIZE + height].copy_from_slice(&glyph[..height]);
+    }
+    fonts.glyph_height = height;
+    fonts.weight = regular;
+    fonts.current = "psf";
+    upload(&fonts);
+    Ok(())
+}
+
+/// Switches every console to the text mode in `TEXT_MODES` with the given size and scales the
+/// current font to its cell height.
+pub fn set_mode(width: usize, height: usize) -> Result<(), &'static str> {
+    text_mode_shown()?;
+    let mode = TEXT_MODES
+        .iter()
+        .find(|mode| mode.width == width && mode.height == height)
+        .ok_or("Unsupported text mode")?;
+    let previous = (self::width(), self::height());
+    let status = status_row();
+    for writer in WRITERS.iter() {
+        let mut writer = writer.lock();
+        writer.begin_update();
+        if let Some(row) = status {
+            writer.clear_row(row);
+        }
+    }
+    mode.timing.write();
+    unsafe {
+        SIZE = (mode.width, mode.height);
+    }
+    upload(&FONT.lock());
+    for writer in WRITERS.iter() {
+        let mut writer = writer.lock();
+        writer.fit_mode(previous);
+        writer.end_update();
+        writer.flush();
+    }
+    Ok(())
+}
+
 /// A combination of a foreground and a background color.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
     /// Create a new `ColorCode` with the given foreground and background colors.
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    pub fn foreground(self) -> Color {
+        Color::from_index(self.0 & 0x0F)
+    }
+
+    pub fn background(self) -> Color {
+        Color::from_index(self.0 >> 4)
+    }
 }
 
 /// A screen character in the VGA text buffer, consisting of an ASCII character and a `ColorCode`.
@@ -79,93 +402,1104 @@ struct ScreenChar {
     color_code: ColorCode,
 }
 
-/// The height of the text buffer (normally 25 lines).
-const BUFFER_HEIGHT: usize = 25;
-/// The width of the text buffer (normally 80 columns).
-const BUFFER_WIDTH: usize = 80;
+/// The number of rows in the text buffer, enough for the tallest text mode. Only the first
+/// `height()` are shown.
+pub const BUFFER_HEIGHT: usize = 60;
+/// The distance between rows in the text buffer, enough for the widest text mode. Only the
+/// first `width()` characters of a row are shown.
+pub const BUFFER_WIDTH: usize = 90;
 
-/// A structure representing the VGA text buffer.
-#[repr(transparent)]
+/// A text mode that can be selected with `set_mode`.
+pub struct TextMode {
+    pub width: usize,
+    pub height: usize,
+    timing: &'static vga::Timing,
+}
+
+pub const TEXT_MODES: [TextMode; 4] = [
+    TextMode { width: 80, height: 25, timing: &vga::TEXT_80X25 },
+    TextMode { width: 80, height: 50, timing: &vga::TEXT_80X50 },
+    TextMode { width: 90, height: 30, timing: &vga::TEXT_90X30 },
+    TextMode { width: 90, height: 60, timing: &vga::TEXT_90X60 },
+];
+
+/// Columns and rows of the current text mode.
+static mut SIZE: (usize, usize) = (80, 25);
+
+/// Returns the number of columns shown in the current text mode.
+pub fn width() -> usize {
+    unsafe { SIZE.0 }
+}
+
+/// Returns the number of rows shown in the current text mode.
+pub fn height() -> usize {
+    unsafe { SIZE.1 }
+}
+
+/// A copy of the whole screen together with the cursor position and colors, taken with
+/// `Writer::save` and put back with `Writer::restore`.
+#[derive(Clone, Copy)]
+pub struct Screen {
+    chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    wrapped: [bool; BUFFER_HEIGHT],
+    row_position: usize,
+    column_position: usize,
+    color_code: ColorCode,
+    /// Columns and rows of the text mode the screen was taken in.
+    size: (usize, usize),
+}
+
+impl Screen {
+    /// Returns the number of columns and rows of the text mode the screen was taken in.
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    /// Returns the CP437 character and colors of a cell.
+    pub fn cell(&self, row: usize, col: usize) -> (u8, ColorCode) {
+        let character = self.chars[row][col];
+        (character.ascii_character, character.color_code)
+    }
+}
+
+/// The VGA text buffer. Rows are `BUFFER_WIDTH` characters apart in every text mode.
+const VGA_TEXT: *mut ScreenChar = 0xb8000 as *mut ScreenChar;
+
+/// A console's screen contents in memory, laid out like the VGA text buffer.
 struct Buffer {
-    chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+/// States of the escape sequence parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    /// Plain text.
+    Ground,
+    /// After an ESC byte.
+    Escape,
+    /// Inside a control sequence (`ESC [`).
+    Csi,
+}
+
+/// The maximum number of numeric parameters kept for a control sequence.
+const MAX_PARAMETERS: usize = 8;
+
+/// Maps the ANSI color numbers (black, red, green, yellow, blue, magenta, cyan, white) onto
+/// the VGA palette. The mapping is its own inverse, so it also maps VGA colors 0-7 to ANSI.
+pub const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+/// A complete escape sequence recognized by `Ansi::parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sequence {
+    /// `ESC 7`
+    SaveCursor,
+    /// `ESC 8`
+    RestoreCursor,
+    /// A control sequence ending in the given byte; its parameters are kept by the parser.
+    Control(u8),
+}
+
+/// Parser state for ANSI/VT100 escape sequences, shared by the text mode and VBE consoles.
+pub struct Ansi {
+    state: EscapeState,
+    parameters: [u16; MAX_PARAMETERS],
+    count: usize,
+    private: bool,
+    /// Whether SGR 1 is active; bold is shown as the bright variant of the foreground.
+    bold: bool,
+}
+
+impl Ansi {
+    pub const fn new() -> Ansi {
+        Ansi {
+            state: EscapeState::Ground,
+            parameters: [0; MAX_PARAMETERS],
+            count: 0,
+            private: false,
+            bold: false,
+        }
+    }
+
+    /// Returns `true` while in the middle of an escape sequence.
+    pub fn pending(&self) -> bool {
+        self.state != EscapeState::Ground
+    }
+
+    /// Starts an escape sequence after an ESC byte.
+    pub fn escape(&mut self) {
+        self.state = EscapeState::Escape;
+    }
+
+    /// Feeds one byte of an escape sequence to the parser and returns the sequence it completes.
+    pub fn parse(&mut self, byte: u8) -> Option<Sequence> {
+        match self.state {
+            EscapeState::Escape => {
+                self.state = EscapeState::Ground;
+                match byte {
+                    b'[' => {
+                        self.state = EscapeState::Csi;
+                        self.parameters = [0; MAX_PARAMETERS];
+                        self.count = 0;
+                        self.private = false;
+                        None
+                    }
+                    b'7' => Some(Sequence::SaveCursor),
+                    b'8' => Some(Sequence::RestoreCursor),
+                    _ => None,
+                }
+            }
+            EscapeState::Csi => match byte {
+                b'0'..=b'9' => {
+                    if self.count == 0 {
+                        self.count = 1;
+                    }
+                    let parameter = &mut self.parameters[self.count - 1];
+                    *parameter = parameter.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                    None
+                }
+                b';' => {
+                    if self.count == 0 {
+                        self.count = 1;
+                    }
+                    if self.count < MAX_PARAMETERS {
+                        self.count += 1;
+                    }
+                    None
+                }
+                b'?' => {
+                    self.private = true;
+                    None
+                }
+                0x40..=0x7e => {
+                    self.state = EscapeState::Ground;
+                    Some(Sequence::Control(byte))
+                }
+                _ => None,
+            },
+            EscapeState::Ground => None,
+        }
+    }
+
+    /// Returns `true` if the last control sequence was a private one (`CSI ? ...`).
+    pub fn private(&self) -> bool {
+        self.private
+    }
+
+    /// Returns the control sequence parameter at `index`, or `default` if it is missing or 0.
+    pub fn parameter(&self, index: usize, default: usize) -> usize {
+        match self.parameters[..self.count].get(index) {
+            Some(&value) if value != 0 => value as usize,
+            _ => default,
+        }
+    }
+
+    /// SGR: applies each parameter of a `CSI ... m` sequence to `color_code`. SGR 0, 39 and 49
+    /// go back to the colors of `default_color`.
+    pub fn graphic_rendition(&mut self, mut color_code: ColorCode, default_color: ColorCode) -> ColorCode {
+        for index in 0..self.count.max(1) {
+            let parameter = self.parameters[index];
+            let mut foreground = color_code.0 & 0x0F;
+            let mut background = color_code.0 >> 4;
+            let bright = if self.bold { 0x08 } else { 0x00 };
+            match parameter {
+                0 => {
+                    foreground = default_color.0 & 0x0F;
+                    background = default_color.0 >> 4;
+                    self.bold = false;
+                }
+                1 => {
+                    foreground |= 0x08;
+                    self.bold = true;
+                }
+                22 => {
+                    if self.bold {
+                        foreground &= !0x08;
+                    }
+                    self.bold = false;
+                }
+                30..=37 => foreground = ANSI_COLORS[(parameter - 30) as usize] | bright,
+                39 => foreground = default_color.0 & 0x0F,
+                40..=47 => background = ANSI_COLORS[(parameter - 40) as usize],
+                49 => background = default_color.0 >> 4,
+                90..=97 => foreground = ANSI_COLORS[(parameter - 90) as usize] | 0x08,
+                100..=107 => background = ANSI_COLORS[(parameter - 100) as usize] | 0x08,
+                _ => (),
+            }
+            color_code = ColorCode(background << 4 | foreground);
+        }
+        color_code
+    }
+}
+
+/// The distance between tab stops.
+const TAB_WIDTH: usize = 8;
+
+/// The number of lines kept after they scroll off the top of the screen.
+const SCROLLBACK_LINES: usize = 500;
+
+const BLANK: ScreenChar = ScreenChar {
+    ascii_character: b' ',
+    color_code: ColorCode::new(Color::White, Color::Black),
+};
+
+/// Lines that scrolled off the screen, kept in a ring, plus a copy of the live screen that is
+/// put back when the user returns from browsing them.
+struct History {
+    lines: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_LINES],
+    start: usize,
+    count: usize,
+    live: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    /// How many lines the view is scrolled back; 0 shows the live screen.
+    offset: usize,
+}
+
+const EMPTY_HISTORY: History = History {
+    lines: [[BLANK; BUFFER_WIDTH]; SCROLLBACK_LINES],
+    start: 0,
+    count: 0,
+    live: [[BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    offset: 0,
+};
+
+static mut HISTORIES: [History; CONSOLES] = [EMPTY_HISTORY; CONSOLES];
+
+/// Where the status row is kept when it is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusPosition {
+    Top,
+    Bottom,
+}
+
+static mut STATUS: Option<StatusPosition> = None;
+
+/// Returns where the status row is shown, or `None` if it is disabled.
+pub fn status_position() -> Option<StatusPosition> {
+    unsafe { STATUS }
+}
+
+fn status_row() -> Option<usize> {
+    match status_position() {
+        Some(StatusPosition::Top) => Some(0),
+        Some(StatusPosition::Bottom) => Some(height() - 1),
+        None => None,
+    }
+}
+
+/// The first row text is written to, below the status row if it is at the top.
+fn text_top() -> usize {
+    if status_position() == Some(StatusPosition::Top) { 1 } else { 0 }
+}
+
+/// The last row text is written to, above the status row if it is at the bottom.
+fn text_bottom() -> usize {
+    if status_position() == Some(StatusPosition::Bottom) { height() - 2 } else { height() - 1 }
+}
+
+/// Reserves the top or bottom row of every console for the status row, or gives it back to
+/// the text with `None`. The text area and scrolling regions of all writers shrink or grow
+/// accordingly.
+pub fn set_status_position(position: Option<StatusPosition>) {
+    if position == status_position() {
+        return;
+    }
+    let previous = status_row();
+    unsafe {
+        STATUS = position;
+    }
+    for writer in WRITERS.iter() {
+        writer.lock().fit_text_area(previous);
+    }
+}
+
+/// Draws the status row of the visible console: `left` at its start and `right` aligned to
+/// its end in `right_color`, on a bar in `color`. Does nothing while the status row is
+/// disabled.
+pub fn draw_status(left: &str, right: &str, color: ColorCode, right_color: ColorCode) {
+    let row = match status_row() {
+        Some(row) => row,
+        None => return,
+    };
+    let mut writer = WRITERS[console::visible()].lock();
+    writer.toggle_overlay();
+    for col in 0..width() {
+        writer.put(row, col, ScreenChar {
+            ascii_character: b' ',
+            color_code: color,
+        });
+    }
+    for (col, character) in left.chars().take(width()).enumerate() {
+        writer.put(row, col, ScreenChar {
+            ascii_character: cp437::encode(character),
+            color_code: color,
+        });
+    }
+    let start = width().saturating_sub(right.chars().count());
+    for (col, character) in (start..width()).zip(right.chars()) {
+        writer.put(row, col, ScreenChar {
+            ascii_character: cp437::encode(character),
+            color_code: right_color,
+        });
+    }
+    writer.toggle_overlay();
+    writer.flush();
+}
+
+const EMPTY_BUFFER: Buffer = Buffer {
+    chars: [[BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT],
+};
+
+/// The screen contents of each console. Writers only draw here; the visible console's rows
+/// are copied to the VGA text buffer by `Writer::flush`.
+static mut SHADOWS: [Buffer; CONSOLES] = [EMPTY_BUFFER; CONSOLES];
+
+fn shadow(console: usize) -> &'static mut Buffer {
+    unsafe { &mut (*core::ptr::addr_of_mut!(SHADOWS))[console] }
+}
+
+/// Shows console `to` instead of console `from`, handing the VGA text buffer and the hardware
+/// cursor over to its writer.
+pub fn switch_console(from: usize, to: usize) {
+    let mut writer = WRITERS[from].lock();
+    writer.snap_back();
+    writer.set_pointer(None);
+    writer.set_selection(None);
+    writer.visible = false;
+    drop(writer);
+
+    let mut writer = WRITERS[to].lock();
+    writer.visible = true;
+    writer.dirty = u64::MAX;
+    writer.flush();
+    writer.program_cursor_shape();
+    writer.program_cursor_visible(writer.cursor_visible);
+    writer.update_cursor();
+}
+
+/// Copies the visible console's pending changes to the screen. Called while waiting for
+/// input, since output that scrolls is only flushed once per screenful of lines.
+pub fn flush() {
+    if let Some(mut writer) = WRITERS[console::visible()].try_lock() {
+        writer.flush();
+    }
 }
 
 /// A writer type that allows writing ASCII bytes and strings to an underlying `Buffer`.
 ///
-/// Wraps lines at `BUFFER_WIDTH`. Supports newline characters and implements the
+/// Wraps lines at the width of the text mode. Supports newline characters and implements the
 /// `core::fmt::Write` trait.
+///
+/// Output starts on the bottom row of the text area, which is the whole screen unless the
+/// status row takes its first or last row. ANSI/VT100 escape sequences can move the cursor anywhere,
+/// change colors and restrict scrolling to a region of rows between `scroll_top` and
+/// `scroll_bottom`.
+///
+/// The mouse pointer and the current selection are drawn on top of the text by inverting the
+/// attributes of the cells they cover; this overlay is removed while text is written so the
+/// inversion never ends up on shifted content.
+///
+/// Lines scrolled off the top are kept in a scrollback history that can be browsed with
+/// `scroll_view`; any new output returns the view to the live screen.
 pub struct Writer {
     column_position: usize,
+    row_position: usize,
+    /// Marks rows that continue a line wrapped at the end of a row, so backspace can move back
+    /// across the wrap.
+    wrapped: [bool; BUFFER_HEIGHT],
     color_code: ColorCode,
+    /// The colors chosen with `color`, restored by SGR 0, 39 and 49.
+    default_color: ColorCode,
     buffer: &'static mut Buffer,
+    /// Rows of `buffer` changed since the last `flush`, one bit per row.
+    dirty: u64,
+    /// Lines scrolled since the last `flush`.
+    scrolled: usize,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    ansi: Ansi,
+    /// Cursor row, column and colors saved with `ESC 7` or `CSI s`.
+    saved: (usize, usize, ColorCode),
+    pointer: Option<(usize, usize)>,
+    selection: Option<(usize, usize)>,
+    cursor_visible: bool,
+    cursor_shape: CursorShape,
+    history: &'static mut History,
+    /// Whether this console is shown, which makes `flush` copy `buffer` to the VGA text buffer
+    /// and this writer the owner of the hardware cursor.
+    visible: bool,
 }
 
 impl Writer {
-    /// Writes an ASCII byte to the buffer.
+    /// Creates the writer of a console; the first one starts out visible.
+    fn new(console: usize) -> Writer {
+        Writer {
+            column_position: 0,
+            row_position: height() - 1,
+            wrapped: [false; BUFFER_HEIGHT],
+            color_code: ColorCode::new(Color::White, Color::Black),
+            default_color: ColorCode::new(Color::White, Color::Black),
+            buffer: shadow(console),
+            dirty: u64::MAX,
+            scrolled: 0,
+            scroll_top: 0,
+            scroll_bottom: height() - 1,
+            ansi: Ansi::new(),
+            saved: (height() - 1, 0, ColorCode::new(Color::White, Color::Black)),
+            pointer: None,
+            selection: None,
+            cursor_visible: true,
+            cursor_shape: CursorShape::Underline,
+            history: unsafe { &mut (*core::ptr::addr_of_mut!(HISTORIES))[console] },
+            visible: console == 0,
+        }
+    }
+
+    /// Writes a byte to the buffer.
     ///
-    /// Wraps lines at `BUFFER_WIDTH`. Supports the `\n` newline character.
+    /// Wraps lines at `width()`. Supports the `\n` newline, `\r` carriage return, `\t`
+    /// tab and `0x08` backspace characters; any other byte is drawn as its CP437 glyph.
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
+            b'\r' => self.column_position = 0,
+            b'\t' => {
+                let col = self.column_position.min(width() - 1);
+                self.column_position = ((col / TAB_WIDTH + 1) * TAB_WIDTH).min(width() - 1);
+            }
+            0x08 => self.backspace(),
+            byte => self.write_glyph(byte),
+        }
+    }
+
+    /// Draws a CP437 glyph at the cursor and advances it, even for bytes that `write_byte`
+    /// treats as control characters.
+    fn write_glyph(&mut self, byte: u8) {
+        if self.column_position >= width() {
+            self.new_line();
+            self.wrapped[self.row_position] = true;
+        }
+
+        let row = self.row_position;
+        let col = self.column_position;
+
+        let color_code = self.color_code;
+        self.put(row, col, ScreenChar {
+            ascii_character: byte,
+            color_code,
+        });
+        self.column_position += 1;
+    }
+
+    /// Writes the given string to the buffer.
+    ///
+    /// Wraps lines at `width()`. Supports the control characters handled by `write_byte`
+    /// and ANSI escape sequences. Other characters are drawn with their code page 437 glyph,
+    /// or `0xFE` if CP437 has none.
+    fn write_string(&mut self, s: &str) {
+        for character in s.chars() {
+            if self.ansi.pending() {
+                let mut utf8 = [0; 4];
+                for &byte in character.encode_utf8(&mut utf8).as_bytes() {
+                    self.escape_byte(byte);
                 }
+                continue;
+            }
+            match character {
+                // start of an escape sequence
+                '\x1b' => self.ansi.escape(),
+                // newline, carriage return, tab or backspace
+                '\n' | '\r' | '\t' | '\x08' => self.write_byte(character as u8),
+                // printable ASCII, or anything CP437 has a glyph for
+                character => self.write_glyph(cp437::encode(character)),
+            }
+        }
+        self.update_cursor();
+    }
 
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
+    /// Moves the cursor one cell back and blanks that cell. At the start of a row that a long
+    /// line wrapped into, the cursor moves back to the end of the previous row.
+    fn backspace(&mut self) {
+        if self.column_position > 0 {
+            self.column_position = self.column_position.min(width()) - 1;
+        } else if self.row_position > 0 && self.wrapped[self.row_position] {
+            self.wrapped[self.row_position] = false;
+            self.row_position -= 1;
+            self.column_position = width() - 1;
+        } else {
+            return;
+        }
+        let col = self.column_position;
+        self.clear_cells(self.row_position, col, col + 1);
+    }
+
+    /// Feeds one byte of an escape sequence to the parser.
+    fn escape_byte(&mut self, byte: u8) {
+        match self.ansi.parse(byte) {
+            Some(Sequence::SaveCursor) => self.save_cursor(),
+            Some(Sequence::RestoreCursor) => self.restore_cursor(),
+            Some(Sequence::Control(command)) => self.control_sequence(command),
+            None => (),
+        }
+    }
+
+    /// Returns the control sequence parameter at `index`, or `default` if it is missing or 0.
+    fn parameter(&self, index: usize, default: usize) -> usize {
+        self.ansi.parameter(index, default)
+    }
 
-                let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code,
-                });
-                self.column_position += 1;
+    /// Executes a complete control sequence ending in `command`.
+    fn control_sequence(&mut self, command: u8) {
+        if self.ansi.private() {
+            // DECTCEM: `CSI ?25h` shows and `CSI ?25l` hides the cursor.
+            if self.parameter(0, 0) == 25 {
+                match command {
+                    b'h' => self.set_cursor_visible(true),
+                    b'l' => self.set_cursor_visible(false),
+                    _ => (),
+                }
+            }
+            return;
+        }
+        let count = self.parameter(0, 1);
+        let col = self.column_position.min(width() - 1);
+        match command {
+            b'A' => self.row_position = self.row_position.saturating_sub(count).max(text_top()),
+            b'B' => self.row_position = (self.row_position + count).min(text_bottom()),
+            b'C' => self.column_position = (col + count).min(width() - 1),
+            b'D' => self.column_position = col.saturating_sub(count),
+            b'H' | b'f' => {
+                self.row_position = (text_top() + self.parameter(0, 1) - 1).min(text_bottom());
+                self.column_position = (self.parameter(1, 1) - 1).min(width() - 1);
             }
+            b'J' => self.erase_in_display(self.parameter(0, 0)),
+            b'K' => self.erase_in_line(self.parameter(0, 0)),
+            b'm' => self.select_graphic_rendition(),
+            b'r' => {
+                // rows are counted from the top of the text area
+                let top = text_top() + self.parameter(0, 1) - 1;
+                let bottom = text_top() + self.parameter(1, text_bottom() + 1 - text_top()) - 1;
+                if top < bottom && bottom <= text_bottom() {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                } else {
+                    self.scroll_top = text_top();
+                    self.scroll_bottom = text_bottom();
+                }
+                self.row_position = text_top();
+                self.column_position = 0;
+            }
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            _ => (),
         }
     }
 
-    /// Writes the given ASCII string to the buffer.
-    ///
-    /// Wraps lines at `BUFFER_WIDTH`. Supports the `\n` newline character. Does **not**
-    /// support strings with non-ASCII characters, since they can't be printed in the VGA text
-    /// mode.
-    fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
+    fn save_cursor(&mut self) {
+        self.saved = (self.row_position, self.column_position, self.color_code);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (row, col, color_code) = self.saved;
+        self.row_position = row.clamp(text_top(), text_bottom());
+        self.column_position = col;
+        self.color_code = color_code;
+    }
+
+    /// Blanks the cells of a row from `start` up to but not including `end`.
+    fn clear_cells(&mut self, row: usize, start: usize, end: usize) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        for col in start..end {
+            self.put(row, col, blank);
+        }
+    }
+
+    /// EL: 0 erases to the end of the line, 1 to its start and 2 the whole line.
+    fn erase_in_line(&mut self, mode: usize) {
+        let row = self.row_position;
+        let col = self.column_position.min(width() - 1);
+        match mode {
+            0 => self.clear_cells(row, col, width()),
+            1 => self.clear_cells(row, 0, col + 1),
+            2 => self.clear_row(row),
+            _ => (),
+        }
+    }
+
+    /// ED: 0 erases to the end of the screen, 1 to its start and 2 (or 3) the whole screen.
+    fn erase_in_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for row in self.row_position + 1..=text_bottom() {
+                    self.clear_row(row);
+                }
             }
+            1 => {
+                for row in text_top()..self.row_position {
+                    self.clear_row(row);
+                }
+                self.erase_in_line(1);
+            }
+            2 | 3 => {
+                for row in text_top()..=text_bottom() {
+                    self.clear_row(row);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// SGR: applies each parameter of a `CSI ... m` sequence to the current colors.
+    fn select_graphic_rendition(&mut self) {
+        self.color_code = self.ansi.graphic_rendition(self.color_code, self.default_color);
+    }
+
+    /// Moves the hardware cursor to the cell the next character will be written to.
+    fn update_cursor(&self) {
+        if !self.visible {
+            return;
         }
+        let col = self.column_position.min(width() - 1);
+        let position = self.row_position * BUFFER_WIDTH + col;
+        write_crtc(0x0F, (position & 0xFF) as u8);
+        write_crtc(0x0E, (position >> 8) as u8);
+    }
+
+    /// Shows or hides the hardware cursor.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+        self.program_cursor_visible(visible && self.history.offset == 0);
     }
 
-    /// Shifts all lines one line up and clears the last row.
+    fn program_cursor_visible(&self, visible: bool) {
+        if !self.visible {
+            return;
+        }
+        let start = read_crtc(0x0A);
+        write_crtc(0x0A, if visible { start & !0x20 } else { start | 0x20 });
+    }
+
+    /// Changes the shape of the hardware cursor, keeping its visibility.
+    ///
+    /// The scan lines are derived from the current character height, so the shapes also fit
+    /// fonts other than the default 8x16 one.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+        self.program_cursor_shape();
+    }
+
+    fn program_cursor_shape(&self) {
+        if !self.visible {
+            return;
+        }
+        let last = read_crtc(0x09) & 0x1F;
+        let (start, end) = match self.cursor_shape {
+            CursorShape::Underline => (last.saturating_sub(1), last),
+            CursorShape::Block => (0, last),
+        };
+        let hidden = read_crtc(0x0A) & 0x20;
+        write_crtc(0x0A, hidden | start);
+        write_crtc(0x0B, (read_crtc(0x0B) & 0xE0) | end);
+    }
+
+    /// Moves to the start of the next row, scrolling the scroll region up when the cursor is
+    /// on its last row.
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
-            }
+        if self.row_position == self.scroll_bottom {
+            self.scroll_up();
+        } else if self.row_position < text_bottom() {
+            self.row_position += 1;
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
         self.column_position = 0;
     }
 
+    /// Shifts the lines of the scroll region one line up and clears its last row. Lines
+    /// leaving the top of the screen are kept in the scrollback history.
+    fn scroll_up(&mut self) {
+        if self.scroll_top == text_top() {
+            self.push_history(self.scroll_top);
+        }
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        self.buffer.chars.copy_within(top + 1..=bottom, top);
+        self.wrapped.copy_within(top + 1..=bottom, top);
+        self.wrapped[top] = false;
+        self.mark_dirty(top, bottom);
+        self.clear_row(bottom);
+        self.scrolled += 1;
+        if self.scrolled >= height() {
+            self.flush();
+        }
+    }
+
     /// Clears a row by overwriting it with blank characters.
     fn clear_row(&mut self, row: usize) {
         let blank = ScreenChar {
             ascii_character: b' ',
             color_code: self.color_code,
         };
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for col in 0..width() {
+            self.put(row, col, blank);
         }
+        self.wrapped[row] = false;
     }
     
+    /// Appends a row of the screen to the scrollback history, blank beyond the columns shown.
+    fn push_history(&mut self, row: usize) {
+        let history = &mut *self.history;
+        let slot = (history.start + history.count) % SCROLLBACK_LINES;
+        for col in 0..BUFFER_WIDTH {
+            history.lines[slot][col] = if col < width() { self.buffer.chars[row][col] } else { BLANK };
+        }
+        if history.count < SCROLLBACK_LINES {
+            history.count += 1;
+        } else {
+            history.start = (history.start + 1) % SCROLLBACK_LINES;
+        }
+    }
+
+    /// Scrolls the view through the history by `lines`; positive values move back towards
+    /// older output and negative values towards the live screen.
+    pub fn scroll_view(&mut self, lines: isize) {
+        let offset = (self.history.offset as isize + lines).clamp(0, self.history.count as isize) as usize;
+        if offset == self.history.offset {
+            return;
+        }
+        self.toggle_overlay();
+        if self.history.offset == 0 {
+            for row in 0..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    self.history.live[row][col] = self.buffer.chars[row][col];
+                }
+            }
+        }
+        self.history.offset = offset;
+        let first = self.history.count - offset;
+        let top = text_top();
+        for row in top..=text_bottom() {
+            let line = first + row - top;
+            for col in 0..BUFFER_WIDTH {
+                let character = if line < self.history.count {
+                    self.history.lines[(self.history.start + line) % SCROLLBACK_LINES][col]
+                } else {
+                    self.history.live[top + line - self.history.count][col]
+                };
+                self.put(row, col, character);
+            }
+        }
+        self.toggle_overlay();
+        self.program_cursor_visible(self.cursor_visible && offset == 0);
+    }
+
+    /// Returns the view to the live screen if it is scrolled back.
+    pub fn snap_back(&mut self) {
+        let offset = self.history.offset;
+        self.scroll_view(-(offset as isize));
+    }
+
+    /// Adapts the writer to a status row that was just moved, enabled or disabled; `previous`
+    /// is the row it used to occupy. Text on a row the status row now covers is scrolled out
+    /// of the way into the history.
+    fn fit_text_area(&mut self, previous: Option<usize>) {
+        self.begin_update();
+        if let Some(row) = previous {
+            self.clear_row(row);
+        }
+        self.scroll_top = 0;
+        self.scroll_bottom = height() - 1;
+        match status_row() {
+            Some(0) => {
+                self.push_history(0);
+                self.clear_row(0);
+            }
+            Some(row) if self.row_position == row => {
+                self.scroll_up();
+                self.row_position -= 1;
+            }
+            _ => (),
+        }
+        self.scroll_top = text_top();
+        self.scroll_bottom = text_bottom();
+        self.row_position = self.row_position.clamp(text_top(), text_bottom());
+        self.end_update();
+    }
+
+    /// Adapts the writer to a text mode that was just set; `previous` is the number of columns
+    /// and rows of the old one. Cells that come into view are blanked, and if the cursor row
+    /// is no longer shown, the text is scrolled up into the history until it is.
+    fn fit_mode(&mut self, previous: (usize, usize)) {
+        let (previous_width, previous_height) = previous;
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        for row in 0..previous_height.min(height()) {
+            for col in previous_width..width() {
+                self.put(row, col, blank);
+            }
+        }
+        for row in previous_height..height() {
+            self.clear_row(row);
+        }
+
+        let shift = self.row_position.saturating_sub(text_bottom());
+        let top = text_top();
+        for row in top..top + shift {
+            self.push_history(row);
+        }
+        if shift > 0 {
+            for row in top..=text_bottom() {
+                for col in 0..width() {
+                    let character = self.buffer.chars[row + shift][col];
+                    self.put(row, col, character);
+                }
+                self.wrapped[row] = self.wrapped[row + shift];
+            }
+        }
+        self.row_position -= shift;
+        self.dirty = u64::MAX;
+        self.scroll_top = text_top();
+        self.scroll_bottom = text_bottom();
+        self.program_cursor_shape();
+        self.program_cursor_visible(self.cursor_visible);
+    }
+
+    /// Restores the default colors, drops the pointer and selection overlay and blanks the
+    /// screen, leaving the writer at the start of the bottom row.
+    pub fn reset(&mut self) {
+        self.pointer = None;
+        self.selection = None;
+        self.history.offset = 0;
+        self.ansi = Ansi::new();
+        self.saved = (height() - 1, 0, ColorCode::new(Color::White, Color::Black));
+        self.color_code = ColorCode::new(Color::White, Color::Black);
+        self.default_color = self.color_code;
+        self.scroll_top = text_top();
+        self.scroll_bottom = text_bottom();
+        for row in text_top()..=text_bottom() {
+            self.clear_row(row);
+        }
+        self.row_position = text_bottom();
+        self.column_position = 0;
+        self.update_cursor();
+        self.set_cursor_visible(true);
+    }
+
     pub fn color(&mut self, foreground: Color, background: Color) {
         self.color_code = ColorCode::new(foreground, background);
+        self.default_color = self.color_code;
+    }
+
+    /// Returns the colors new text is written with.
+    pub fn color_code(&self) -> ColorCode {
+        self.color_code
+    }
+
+    /// Returns to the live screen and removes the overlay before the screen is modified.
+    fn begin_update(&mut self) {
+        self.snap_back();
+        self.toggle_overlay();
+    }
+
+    /// Puts the overlay back, flushes the changes and moves the hardware cursor after the
+    /// screen was modified. Once lines have scrolled, the screen is only flushed a screenful
+    /// at a time, and what is left when output stops by `flush` from `poll` or `read_char`.
+    fn end_update(&mut self) {
+        self.toggle_overlay();
+        if self.scrolled == 0 {
+            self.flush();
+        }
+        self.update_cursor();
+    }
+
+    /// Writes a cell and marks its row for the next `flush`.
+    fn put(&mut self, row: usize, col: usize, character: ScreenChar) {
+        self.buffer.chars[row][col] = character;
+        self.dirty |= 1 << row;
+    }
+
+    /// Marks rows `first` to `last` (inclusive) for the next `flush`.
+    fn mark_dirty(&mut self, first: usize, last: usize) {
+        for row in first..=last {
+            self.dirty |= 1 << row;
+        }
+    }
+
+    /// Copies the rows changed since the last flush to the VGA text buffer if this console is
+    /// shown and a text mode is set.
+    pub fn flush(&mut self) {
+        self.scrolled = 0;
+        if !self.visible || gfx::active() || vbe::active() {
+            return;
+        }
+        for row in 0..height() {
+            if self.dirty & 1 << row == 0 {
+                continue;
+            }
+            for col in 0..width() {
+                unsafe { VGA_TEXT.add(row * BUFFER_WIDTH + col).write_volatile(self.buffer.chars[row][col]) };
+            }
+        }
+        self.dirty = 0;
+    }
+
+    /// Blanks the screen with the current colors and moves the cursor to the top left corner.
+    pub fn clear(&mut self) {
+        self.begin_update();
+        self.selection = None;
+        for row in text_top()..=text_bottom() {
+            self.clear_row(row);
+        }
+        self.row_position = text_top();
+        self.column_position = 0;
+        self.end_update();
+    }
+
+    /// Writes a character with the given colors to a cell without moving the cursor. Cells
+    /// outside the screen are ignored.
+    pub fn write_at(&mut self, row: usize, col: usize, character: u8, color_code: ColorCode) {
+        self.fill_rect(row, col, 1, 1, character, color_code);
+    }
+
+    /// Returns the character and colors shown in a cell, without the pointer or selection
+    /// highlight. Cells outside the screen read as blanks.
+    pub fn read_at(&self, row: usize, col: usize) -> (u8, ColorCode) {
+        if row >= height() || col >= width() {
+            return (b' ', self.color_code);
+        }
+        let character = self.buffer.chars[row][col];
+        let index = row * BUFFER_WIDTH + col;
+        let selected = matches!(self.selection, Some((start, end)) if start <= index && index <= end);
+        let pointed = self.pointer == Some((row, col));
+        if selected != pointed {
+            (character.ascii_character, ColorCode(character.color_code.0 ^ 0x77))
+        } else {
+            (character.ascii_character, character.color_code)
+        }
+    }
+
+    /// Fills a rectangle of cells with a character and colors, clipped to the screen.
+    pub fn fill_rect(&mut self, row: usize, col: usize, height: usize, width: usize, character: u8, color_code: ColorCode) {
+        self.begin_update();
+        let screen_char = ScreenChar {
+            ascii_character: character,
+            color_code,
+        };
+        for r in row..(row + height).min(self::height()) {
+            for c in col..(col + width).min(self::width()) {
+                self.put(r, c, screen_char);
+            }
+        }
+        self.end_update();
+    }
+
+    /// Moves the cursor, where the next character will be written, clamped to the screen.
+    pub fn set_cursor_pos(&mut self, row: usize, col: usize) {
+        self.row_position = row.min(height() - 1);
+        self.column_position = col.min(width() - 1);
+        self.update_cursor();
+    }
+
+    /// Returns the `(row, col)` the next character will be written to.
+    pub fn cursor_pos(&self) -> (usize, usize) {
+        (self.row_position, self.column_position)
+    }
+
+    /// Takes a copy of the live screen, cursor position and colors.
+    pub fn save(&mut self) -> Screen {
+        self.begin_update();
+        let screen = Screen {
+            chars: self.buffer.chars,
+            wrapped: self.wrapped,
+            row_position: self.row_position,
+            column_position: self.column_position,
+            color_code: self.color_code,
+            size: (width(), height()),
+        };
+        self.end_update();
+        screen
+    }
+
+    /// Puts back a screen taken with `save`, including its cursor position and colors.
+    pub fn restore(&mut self, screen: &Screen) {
+        self.begin_update();
+        self.selection = None;
+        for row in text_top()..=text_bottom() {
+            for col in 0..BUFFER_WIDTH {
+                self.put(row, col, screen.chars[row][col]);
+            }
+        }
+        self.wrapped = screen.wrapped;
+        self.row_position = screen.row_position.clamp(text_top(), text_bottom());
+        self.column_position = screen.column_position;
+        self.color_code = screen.color_code;
+        self.end_update();
+    }
+
+    /// Inverts the colors of a single cell, which is its own inverse.
+    fn invert_cell(&mut self, row: usize, col: usize) {
+        let mut character = self.buffer.chars[row][col];
+        character.color_code = ColorCode(character.color_code.0 ^ 0x77);
+        self.put(row, col, character);
+    }
+
+    /// Draws or removes the pointer and selection highlight.
+    fn toggle_overlay(&mut self) {
+        if let Some((start, end)) = self.selection {
+            for index in start..=end {
+                if index % BUFFER_WIDTH < width() {
+                    self.invert_cell(index / BUFFER_WIDTH, index % BUFFER_WIDTH);
+                }
+            }
+        }
+        if let Some((row, col)) = self.pointer {
+            self.invert_cell(row, col);
+        }
+    }
+
+    /// Moves the mouse pointer to the given `(row, col)` cell, or hides it with `None`.
+    pub fn set_pointer(&mut self, pointer: Option<(usize, usize)>) {
+        self.toggle_overlay();
+        self.pointer = pointer;
+        self.toggle_overlay();
+    }
+
+    /// Highlights the cells between two linear cell indices (inclusive), or clears the
+    /// highlight with `None`.
+    pub fn set_selection(&mut self, selection: Option<(usize, usize)>) {
+        self.toggle_overlay();
+        self.selection = selection.map(|(a, b)| if a <= b { (a, b) } else { (b, a) });
+        self.toggle_overlay();
+    }
+
+    /// Copies the text between two linear cell indices (inclusive) into `out`, ending each
+    /// screen row with a newline and dropping its trailing blanks. The text is copied as CP437
+    /// bytes, with the glyphs below 0x20 replaced by `?`. Returns the number of bytes written.
+    pub fn copy_text(&self, start: usize, end: usize, out: &mut [u8]) -> usize {
+        let mut length = 0;
+        let mut index = start;
+        while index <= end {
+            let row = index / BUFFER_WIDTH;
+            let row_end = end.min(row * BUFFER_WIDTH + width() - 1);
+            let mut count = row_end + 1 - index;
+            while count > 0 && self.buffer.chars[row][(index + count - 1) % BUFFER_WIDTH].ascii_character == b' ' {
+                count -= 1;
+            }
+            for cell in index..index + count {
+                let byte = self.buffer.chars[row][cell % BUFFER_WIDTH].ascii_character;
+                if length < out.len() {
+                    out[length] = if byte >= 0x20 { byte } else { b'?' };
+                    length += 1;
+                }
+            }
+            if row_end < end && length < out.len() {
+                out[length] = b'\n';
+                length += 1;
+            }
+            index = row_end + 1;
+        }
+        length
     }
 }
 
@@ -189,9 +1523,23 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
-/// Prints the given formatted string to the VGA text buffer through the global `WRITER` instance.
+/// Prints the given formatted string to the VGA text buffer through the global `WRITER` instance,
+/// or to the graphics screen while the visible console shows graphics.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    WRITER.lock().write_fmt(args).unwrap();
+    if console::current() == console::visible() {
+        if gfx::active() {
+            gfx::print(args);
+            return;
+        }
+        if vbe::active() {
+            vbe::print(args);
+            return;
+        }
+    }
+    let mut writer = WRITER.lock();
+    writer.begin_update();
+    writer.write_fmt(args).unwrap();
+    writer.end_update();
 }
\ No newline at end of file